@@ -0,0 +1,96 @@
+use std::{f64::consts::PI, io, path::Path, sync::Arc};
+
+use crate::{image::Image, random, vec3::Vec3};
+
+/// Shape of the lens opening, which out-of-focus highlights take on.
+#[derive(Clone, Debug, Default)]
+#[allow(unused)]
+pub enum Aperture {
+    #[default]
+    Circular,
+    /// Regular polygon formed by `blades` straight blades, turned by
+    /// `rotation` degrees.
+    Polygon { blades: u32, rotation: f64 },
+    /// Arbitrary shape given by a grayscale transmission mask.
+    Mask(Arc<ApertureMask>),
+}
+
+impl Aperture {
+    /// Random point on the aperture, within the unit disk for the circular and
+    /// polygonal shapes and the `-1..1` square for masks.
+    pub fn sample(&self) -> Vec3 {
+        match self {
+            Self::Circular => Vec3::random_in_unit_disk(),
+            Self::Polygon { blades, rotation } => sample_polygon((*blades).max(3), *rotation),
+            Self::Mask(mask) => mask.sample(),
+        }
+    }
+}
+
+/// Picks one of the polygon's triangles fanning out of the center, then a
+/// uniform point inside it.
+fn sample_polygon(blades: u32, rotation: f64) -> Vec3 {
+    let step = 2.0 * PI / f64::from(blades);
+    let k = f64::from(random::gen_range(0..blades));
+    let start = rotation.to_radians() + k * step;
+    let a = Vec3::new(start.cos(), start.sin(), 0.0);
+    let b = Vec3::new((start + step).cos(), (start + step).sin(), 0.0);
+
+    let (mut s, mut t) = (random::random::<f64>(), random::random::<f64>());
+    if s + t > 1.0 {
+        (s, t) = (1.0 - s, 1.0 - t);
+    }
+    s * a + t * b
+}
+
+/// Grayscale image stretched over the `-1..1` square, white being fully open.
+#[derive(Debug)]
+pub struct ApertureMask {
+    width: u32,
+    height: u32,
+    /// Running sum of the transmission of every pixel, row by row.
+    cdf: Vec<f64>,
+}
+
+impl ApertureMask {
+    #[allow(unused)]
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let image = Image::load(path)?;
+        let mut total = 0.0;
+        let cdf = (0..image.height())
+            .flat_map(|j| (0..image.width()).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                total += image.gray(i, j);
+                total
+            })
+            .collect();
+        if total <= 0.0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "aperture mask is fully closed",
+            ));
+        }
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            cdf,
+        })
+    }
+
+    /// Picks a pixel in proportion to its transmission, then a uniform point
+    /// inside it.
+    fn sample(&self) -> Vec3 {
+        let total = self.cdf.last().copied().unwrap_or_default();
+        let u = random::random::<f64>() * total;
+        let n = self
+            .cdf
+            .partition_point(|&c| c <= u)
+            .min(self.cdf.len() - 1);
+        let n = u32::try_from(n).expect("mask too large");
+        let (i, j) = (n % self.width, n / self.width);
+
+        let x = (f64::from(i) + random::random::<f64>()) / f64::from(self.width);
+        let y = (f64::from(j) + random::random::<f64>()) / f64::from(self.height);
+        Vec3::new(2.0 * x - 1.0, 1.0 - 2.0 * y, 0.0)
+    }
+}
@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use crate::{hittable::HitRecord, image::Image, texture::Texture, vec3::Vec3};
+
+/// Step in texture coordinates for the finite differences of procedural
+/// height maps; image height maps step by a pixel.
+const HEIGHT_DELTA: f64 = 1e-3;
+
+/// Detail that tilts the shading normal without changing the geometry.
+#[derive(Clone, Debug)]
+#[allow(unused)]
+pub enum BumpMap {
+    /// A tangent-space normal map: red, green and blue encode the normal
+    /// along `∂p/∂u`, `∂p/∂v` and the surface normal, from `-1` at `0.0` to
+    /// `1` at `1.0` (the OpenGL convention). `strength` scales the tilt.
+    Normal { image: Arc<Image>, strength: f64 },
+    /// Offsets the surface along its normal by `scale` times the height
+    /// texture, in world units, and shades the slopes that makes.
+    Height { height: Texture, scale: f64 },
+}
+
+impl BumpMap {
+    /// Replaces the shading normal of `hit_record` with the mapped one.
+    pub fn apply(&self, hit_record: &mut HitRecord) {
+        let (u, v, p) = (hit_record.u, hit_record.v, hit_record.p);
+        let dpdu = hit_record.tangent;
+        let dpdv = hit_record.bitangent;
+        // Maps are defined against the outward normal.
+        let outward = if hit_record.front_face {
+            hit_record.shading_normal
+        } else {
+            -hit_record.shading_normal
+        };
+
+        let normal = match self {
+            Self::Normal { image, strength } => {
+                let [x, y, z] = match *image.at_uv(u, v) {
+                    [r, g, b] => [2.0 * r - 1.0, 2.0 * g - 1.0, 2.0 * b - 1.0],
+                    _ => return,
+                };
+                let tangent = (dpdu - Vec3::dot(&dpdu, &outward) * outward).unit();
+                let bitangent = Vec3::cross(&outward, &tangent);
+                *strength * (x * tangent + y * bitangent) + z * outward
+            }
+            Self::Height { height, scale } => {
+                let h = |du: f64, dv: f64| {
+                    let p = p + du * dpdu + dv * dpdv;
+                    *scale * height.smooth_factor(u + du, v + dv, &p)
+                };
+                let (du, dv) = height.texel_size().unwrap_or((HEIGHT_DELTA, HEIGHT_DELTA));
+                let dhdu = (h(du, 0.0) - h(-du, 0.0)) / (2.0 * du);
+                let dhdv = (h(0.0, dv) - h(0.0, -dv)) / (2.0 * dv);
+                let dpdu = dpdu + dhdu * outward;
+                let dpdv = dpdv + dhdv * outward;
+                let normal = Vec3::cross(&dpdu, &dpdv);
+                if Vec3::dot(&normal, &outward) < 0.0 {
+                    -normal
+                } else {
+                    normal
+                }
+            }
+        };
+        if normal.near_zero() {
+            return;
+        }
+
+        let normal = normal.unit();
+        hit_record.shading_normal = if hit_record.front_face {
+            normal
+        } else {
+            -normal
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+
+    use super::*;
+    use crate::{color::Color, material::Material, ray::Ray, vec3::Point3};
+
+    /// A height map rising linearly from `0.0` to `1.0` along `u`, in
+    /// `width` pixels.
+    fn ramp(width: u32) -> Texture {
+        let max = width - 1;
+        let mut pgm = format!("P2 {width} 1 {max}\n");
+        for i in 0..width {
+            write!(pgm, "{i} ").unwrap();
+        }
+        let path = std::env::temp_dir().join(format!(
+            "ray-tracing-ramp-{width}-{}.pgm",
+            std::process::id()
+        ));
+        std::fs::write(&path, pgm).unwrap();
+        let image = Image::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        Texture::Image(Arc::new(image))
+    }
+
+    #[test]
+    fn image_height_maps_slope_at_any_resolution() {
+        let mat = Arc::new(Material::lambertian(Color::new(0.5, 0.5, 0.5)));
+        let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        for width in [64, 2048] {
+            let map = BumpMap::Height {
+                height: ramp(width),
+                scale: 0.5,
+            };
+            for u in [0.3, 0.3 + 0.5 / f64::from(width), 0.71] {
+                let mut hit = HitRecord::new(
+                    1.0,
+                    Point3::default(),
+                    &ray,
+                    Vec3::new(0.0, 0.0, 1.0),
+                    mat.clone(),
+                );
+                (hit.u, hit.v) = (u, 0.5);
+                (hit.tangent, hit.bitangent) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+                map.apply(&mut hit);
+
+                // The surface rises by half a unit per unit along `u`.
+                let expected = Vec3::new(-0.5, 0.0, 1.0).unit();
+                assert!(
+                    (hit.shading_normal - expected).len() < 0.01,
+                    "width {width} at u = {u}: {:?}",
+                    hit.shading_normal
+                );
+            }
+        }
+    }
+}
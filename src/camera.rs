@@ -1,33 +1,258 @@
-use indicatif::ParallelProgressIterator;
+use indicatif::ProgressBar;
 use rayon::prelude::*;
-use std::ops::{Div, Mul, Rem};
-
-use rand::prelude::*;
+use std::{
+    f64::consts::PI,
+    io::{self, BufWriter, Write},
+    ops::{Div, Mul},
+    path::PathBuf,
+    sync::Arc,
+};
 
 use crate::{
-    color::{self, Color},
-    hittable::Hittable,
+    aperture::Aperture,
+    color::{self, Color, ColorSpace, Display, Matrix},
+    film::{self, Film, Format},
+    filter::Filter,
+    hittable::{HitRecord, Hittable},
     hittable_list::HittableList,
     interval::Interval,
+    lens::LensSystem,
     material::ScatterResult,
+    medium, random,
     ray::Ray,
+    spectrum::{self, Spectrum, Wavelengths},
+    tile::{self, Tile, TileOrder},
+    tonemap::ToneMap,
     vec3::{Point3, Vec3},
 };
 
+/// A sub-rectangle of the image to render, leaving the projection untouched.
+#[derive(Clone, Copy, Debug)]
+#[allow(unused)]
+pub enum Crop {
+    /// Pixel bounds `x0..x1` by `y0..y1`.
+    Pixels { x0: u32, y0: u32, x1: u32, y1: u32 },
+    /// Bounds as fractions of the image size, from `0.0` to `1.0`.
+    Normalized { x0: f64, y0: f64, x1: f64, y1: f64 },
+}
+
+impl Crop {
+    /// Resolves the crop to pixel bounds inside a `width` by `height` image,
+    /// keeping at least one pixel.
+    fn bounds(self, width: u32, height: u32) -> Tile {
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_sign_loss)]
+        let to_pixel = |t: f64, size: u32| (t.clamp(0.0, 1.0) * f64::from(size)).round() as u32;
+
+        let (x0, y0, x1, y1) = match self {
+            Self::Pixels { x0, y0, x1, y1 } => (x0, y0, x1, y1),
+            Self::Normalized { x0, y0, x1, y1 } => (
+                to_pixel(x0, width),
+                to_pixel(y0, height),
+                to_pixel(x1, width),
+                to_pixel(y1, height),
+            ),
+        };
+        let x0 = x0.min(width - 1);
+        let y0 = y0.min(height - 1);
+        Tile {
+            x0,
+            y0,
+            x1: x1.clamp(x0 + 1, width),
+            y1: y1.clamp(y0 + 1, height),
+        }
+    }
+}
+
+/// How rays leave the camera.
+#[derive(Clone, Debug, Default)]
+#[allow(unused)]
+pub enum Projection {
+    /// Rays fan out from `lookfrom`, covering `vfov` vertically.
+    #[default]
+    Perspective,
+    /// Parallel rays through a `view_width` by `view_height` window in world
+    /// units, so parallel lines stay parallel. The window is stretched if its
+    /// proportions differ from `aspect_ratio`.
+    Orthographic { view_width: f64, view_height: f64 },
+    /// Full 360° by 180° latitude/longitude panorama, centered on `lookat`.
+    /// Expects a 2:1 image.
+    Equirectangular,
+    /// Six square faces side by side, in the order right, left, up, down,
+    /// front, back relative to the view. Expects a 6:1 image.
+    Cubemap,
+    /// Circular fisheye covering `fov` degrees across the image circle, which
+    /// is inscribed in the image.
+    Fisheye { fov: f64, mapping: FisheyeMapping },
+    /// Traces rays through a real lens prescription onto a film whose diagonal
+    /// is `film_diagonal` millimeters, focused at `focus_dist`. Vignetting,
+    /// distortion and bokeh come from the lens itself, so `vfov` and
+    /// `defocus_angle` are ignored, and setting `aperture`, `lens_shift`,
+    /// `focus_tilt` or `physical_camera` as well is an error.
+    Realistic {
+        lens: Arc<LensSystem>,
+        film_diagonal: f64,
+    },
+}
+
+impl Projection {
+    fn is_panoramic(&self) -> bool {
+        matches!(
+            self,
+            Self::Equirectangular | Self::Cubemap | Self::Fisheye { .. }
+        )
+    }
+}
+
+/// How a fisheye lens maps the angle off its axis to distance from the image center.
+#[derive(Clone, Copy, Debug)]
+#[allow(unused)]
+pub enum FisheyeMapping {
+    /// Distance proportional to the angle.
+    Equidistant,
+    /// Equal solid angles cover equal image areas.
+    Equisolid,
+}
+
+/// Renders a left and a right eye image into one frame.
+#[derive(Clone, Copy, Debug)]
+pub struct Stereo {
+    /// Distance between the eyes, in world units.
+    pub interocular: f64,
+    pub convergence: Convergence,
+    pub layout: StereoLayout,
+}
+
+/// Where the lines of sight of the two eyes meet.
+#[derive(Clone, Copy, Debug)]
+#[allow(unused)]
+pub enum Convergence {
+    /// Both eyes look straight ahead and never converge.
+    Parallel,
+    /// Both eyes turn to look at the point `distance` ahead of `lookfrom`.
+    ToeIn { distance: f64 },
+}
+
+/// How the two eye images are arranged in the output.
+#[derive(Clone, Copy, Debug)]
+#[allow(unused)]
+pub enum StereoLayout {
+    /// Left eye on the left, right eye on the right.
+    SideBySide,
+    /// Left eye on top, right eye below.
+    OverUnder,
+}
+
+/// Exposure settings of a real camera, for scenes lit in physical units
+/// (radiance in W/sr/m², world units in meters).
+#[derive(Clone, Copy, Debug)]
+pub struct PhysicalCamera {
+    /// Sensor sensitivity.
+    pub iso: f64,
+    /// Exposure time in seconds.
+    pub shutter_speed: f64,
+    /// Focal length over aperture diameter. Sets the lens opening, replacing
+    /// `defocus_angle`, and the exposure.
+    pub f_number: f64,
+    /// Overrides the exposure value at ISO 100 otherwise derived from the
+    /// other settings.
+    pub exposure_value: Option<f64>,
+    /// Sensor height in millimeters, which with `vfov` gives the focal length.
+    pub sensor_height: f64,
+}
+
+impl Default for PhysicalCamera {
+    fn default() -> Self {
+        Self {
+            iso: 100.0,
+            shutter_speed: 1.0 / 125.0,
+            f_number: 16.0,
+            exposure_value: None,
+            sensor_height: 24.0,
+        }
+    }
+}
+
+impl PhysicalCamera {
+    /// Exposure value at ISO 100 (EV100).
+    fn ev100(&self) -> f64 {
+        self.exposure_value.unwrap_or_else(|| {
+            (self.f_number * self.f_number / self.shutter_speed).log2() - (self.iso / 100.0).log2()
+        })
+    }
+
+    /// Factor from scene radiance to sensor value, following the saturation
+    /// based sensitivity convention: 1.2 = 78 / (100 * 0.65).
+    fn exposure(&self) -> f64 {
+        (1.2 * self.ev100().exp2()).recip()
+    }
+
+    /// Cone angle of the lens opening seen from the plane of focus, in degrees.
+    fn defocus_angle(&self, vfov: f64, focus_dist: f64) -> f64 {
+        let focal_length = self.sensor_height / 2.0 / vfov.to_radians().div(2.0).tan();
+        let aperture_radius = 0.001 * focal_length / self.f_number / 2.0;
+        2.0 * (aperture_radius / focus_dist).atan().to_degrees()
+    }
+}
+
+/// The lens of a `Realistic` projection focused at `focus_dist`, and the
+/// film behind it.
 #[derive(Debug)]
-pub struct Camera {
-    image_width: u32,
-    image_height: u32,
+struct FocusedLens {
+    system: Arc<LensSystem>,
+    /// Film size in world units.
+    film_width: f64,
+    film_height: f64,
+}
+
+/// Camera frame and viewport of one eye.
+#[derive(Debug)]
+struct View {
     center: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
     pixel00_loc: Point3,
     pixel_delta_u: Vec3,
     pixel_delta_v: Vec3,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+    /// Normal of the plane of sharp focus, when tilted away from the film plane.
+    focus_plane_normal: Option<Vec3>,
+    /// Sideways offset of the eye from `center`, applied per ray in panoramic
+    /// projections so every viewing direction gets stereo (omnidirectional stereo).
+    ods_offset: f64,
+}
+
+#[derive(Debug)]
+pub struct Camera {
+    projection: Projection,
+    image_width: u32,
+    image_height: u32,
+    views: Vec<View>,
+    stereo: Option<Stereo>,
+    lens: Option<FocusedLens>,
+    focus_dist: f64,
     defocus_angle: f64,
+    aperture: Aperture,
+    display: Display,
+    /// Converts the XYZ of spectral samples to the working space, when
+    /// rendering spectrally.
+    spectral: Option<Matrix>,
+    /// Converts working space colors to the linear Rec. 709 primaries RGB
+    /// albedos are upsampled to spectra from.
+    to_rec709: Matrix,
+    output_path: Option<PathBuf>,
     samples_per_pixel: u32,
-    pixel_samples_scale: f64,
     max_depth: u32,
+    snapshot_path: Option<PathBuf>,
+    bounds: Tile,
+    tiles: Vec<Tile>,
+    filter: Filter,
+    seed: u64,
+    checkpoint_path: Option<PathBuf>,
+    max_pass_samples: u32,
+    resume: bool,
 }
 
 pub struct Settings {
@@ -41,6 +266,59 @@ pub struct Settings {
     pub vup: Vec3,
     pub defocus_angle: f64,
     pub focus_dist: f64,
+    /// When set, the accumulated image is written here after every progressive
+    /// pass, so a render can be stopped early and still leave a usable image.
+    pub snapshot_path: Option<PathBuf>,
+    /// Edge length in pixels of the square tiles the image is rendered in.
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+    /// Every sample draws its random numbers from this seed, its pixel and its
+    /// index, so a render is reproducible however the work is scheduled.
+    pub seed: u64,
+    /// When set, the accumulated radiance and sample count are saved here
+    /// after every pass.
+    pub checkpoint_path: Option<PathBuf>,
+    /// Most samples per pixel rendered in one pass, so snapshots and
+    /// checkpoints keep coming once the doubling passes grow long.
+    pub max_pass_samples: u32,
+    /// Continue from the checkpoint at `checkpoint_path` instead of starting
+    /// over, adding samples until `samples_per_pixel` is reached.
+    pub resume: bool,
+    /// Renders only this part of the image; the output is the size of the crop.
+    pub crop: Option<Crop>,
+    /// Reconstruction filter weighting each sample into the pixels around it.
+    pub filter: Filter,
+    pub projection: Projection,
+    /// Renders both eyes of a stereo pair; `image_width` and `aspect_ratio`
+    /// then describe a single eye.
+    pub stereo: Option<Stereo>,
+    /// Shape of the thin lens opening set by `defocus_angle`.
+    pub aperture: Aperture,
+    /// Slides the lens parallel to the film, as fractions of the view width
+    /// (rightwards) and height (upwards), reframing without converging
+    /// verticals.
+    pub lens_shift: (f64, f64),
+    /// Turns the plane of sharp focus, in degrees, about the horizontal axis
+    /// (tilt) and the vertical axis (swing), as a tilted lens does.
+    pub focus_tilt: (f64, f64),
+    /// Derives the lens opening and the image brightness from real camera
+    /// settings.
+    pub physical_camera: Option<PhysicalCamera>,
+    /// Brightens (positive) or darkens (negative) the image by this many
+    /// stops before tone mapping.
+    pub exposure_compensation: f64,
+    pub tone_map: ToneMap,
+    /// Color space the scene's RGB values are given and rendered in.
+    pub working_space: ColorSpace,
+    /// Color space of the written images, which are tagged with it.
+    pub output_space: ColorSpace,
+    /// Where to write the final image, in the format its extension names
+    /// (`.ppm`, `.png` or `.exr`). Without it a PPM goes to stdout.
+    pub output_path: Option<PathBuf>,
+    /// Traces wavelengths instead of RGB: every path carries a few
+    /// wavelengths, RGB albedos are upsampled to spectra, and the film
+    /// receives their color.
+    pub spectral: bool,
 }
 
 impl Default for Settings {
@@ -56,6 +334,27 @@ impl Default for Settings {
             vup: Vec3::new(0.0, 1.0, 0.0),
             defocus_angle: 0.6,
             focus_dist: 10.0,
+            snapshot_path: None,
+            tile_size: 32,
+            tile_order: TileOrder::default(),
+            seed: 0,
+            checkpoint_path: None,
+            max_pass_samples: 64,
+            resume: false,
+            crop: None,
+            filter: Filter::default(),
+            projection: Projection::default(),
+            stereo: None,
+            aperture: Aperture::default(),
+            lens_shift: (0.0, 0.0),
+            focus_tilt: (0.0, 0.0),
+            physical_camera: None,
+            exposure_compensation: 0.0,
+            tone_map: ToneMap::default(),
+            working_space: ColorSpace::default(),
+            output_space: ColorSpace::default(),
+            output_path: None,
+            spectral: false,
         }
     }
 }
@@ -73,8 +372,29 @@ impl Camera {
             vup,
             defocus_angle,
             focus_dist,
+            snapshot_path,
+            tile_size,
+            tile_order,
+            seed,
+            checkpoint_path,
+            max_pass_samples,
+            resume,
+            crop,
+            filter,
+            projection,
+            stereo,
+            aperture,
+            lens_shift: (shift_x, shift_y),
+            focus_tilt: (tilt, swing),
+            physical_camera,
+            exposure_compensation,
+            tone_map,
+            working_space,
+            output_space,
+            output_path,
+            spectral,
         }: Settings,
-    ) -> Self {
+    ) -> io::Result<Self> {
         let image_height = match f64::from(image_width) / aspect_ratio {
             #[allow(clippy::cast_possible_truncation)]
             #[allow(clippy::cast_sign_loss)]
@@ -82,114 +402,807 @@ impl Camera {
             _ => 1,
         };
 
-        let center = lookfrom;
-        let viewport_height = 2.0 * vfov.to_radians().div(2.0).tan() * focus_dist;
-        let viewport_width = viewport_height * (f64::from(image_width) / f64::from(image_height));
+        let defocus_angle = physical_camera.map_or(defocus_angle, |physical| {
+            physical.defocus_angle(vfov, focus_dist)
+        });
+        let display = Display::new(
+            physical_camera.map_or(1.0, |physical| physical.exposure())
+                * exposure_compensation.exp2(),
+            tone_map,
+            working_space,
+            output_space,
+        );
+        let spectral = spectral.then(|| spectrum::xyz_to_rgb(working_space));
+        let to_rec709 = working_space.conversion(ColorSpace::Srgb);
+
+        let (projection, lens) = match projection {
+            Projection::Realistic {
+                lens,
+                film_diagonal,
+            } => {
+                // The lens prescription fixes all of these, so asking for
+                // them as well is a mistake rather than something to ignore.
+                let ignored = [
+                    (!matches!(aperture, Aperture::Circular), "aperture"),
+                    ((shift_x, shift_y) != (0.0, 0.0), "lens_shift"),
+                    ((tilt, swing) != (0.0, 0.0), "focus_tilt"),
+                    (physical_camera.is_some(), "physical_camera"),
+                ];
+                if let Some((_, setting)) = ignored.into_iter().find(|(set, _)| *set) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("a realistic lens cannot be combined with {setting}"),
+                    ));
+                }
+                let system = Arc::new(lens.focused(focus_dist).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("the lens cannot focus at {focus_dist}"),
+                    )
+                })?);
+                let aspect = f64::from(image_width) / f64::from(image_height);
+                let diagonal = film_diagonal * 0.001;
+                let film_width = diagonal / (1.0 + aspect.powi(-2)).sqrt();
+                let lens = FocusedLens {
+                    system: Arc::clone(&system),
+                    film_width,
+                    film_height: film_width / aspect,
+                };
+                // Only the focused lens is kept.
+                let projection = Projection::Realistic {
+                    lens: system,
+                    film_diagonal,
+                };
+                (projection, Some(lens))
+            }
+            projection => (projection, None),
+        };
+
+        let (viewport_width, viewport_height) = match &projection {
+            Projection::Perspective => {
+                let viewport_height = 2.0 * vfov.to_radians().div(2.0).tan() * focus_dist;
+                let viewport_width =
+                    viewport_height * (f64::from(image_width) / f64::from(image_height));
+                (viewport_width, viewport_height)
+            }
+            Projection::Orthographic {
+                view_width,
+                view_height,
+            } => (*view_width, *view_height),
+            // Panoramas build their rays from angles and lenses trace them
+            // from the film, neither uses a viewport.
+            _ => (1.0, 1.0),
+        };
 
-        let w = (lookfrom - lookat).unit();
-        let u = Vec3::cross(&vup, &w).unit();
-        let v = Vec3::cross(&w, &u);
+        let view = |lookfrom: Point3, lookat: Point3, ods_offset: f64| {
+            let center = lookfrom;
+            let w = (lookfrom - lookat).unit();
+            let u = Vec3::cross(&vup, &w).unit();
+            let v = Vec3::cross(&w, &u);
 
-        let viewport_u = viewport_width * u;
-        let viewport_v = viewport_height * -v;
+            let viewport_u = viewport_width * u;
+            let viewport_v = viewport_height * -v;
 
-        let pixel_delta_u = viewport_u / f64::from(image_width);
-        let pixel_delta_v = viewport_v / f64::from(image_height);
+            let pixel_delta_u = viewport_u / f64::from(image_width);
+            let pixel_delta_v = viewport_v / f64::from(image_height);
 
-        let viewport_upper_left = center - focus_dist.mul(w) - viewport_u / 2.0 - viewport_v / 2.0;
+            let viewport_upper_left = center - focus_dist.mul(w) - viewport_u / 2.0
+                + shift_x * viewport_u
+                - viewport_v / 2.0
+                - shift_y * viewport_v;
 
-        let pixel00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
+            let pixel00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
 
-        let defocus_radius = focus_dist * (defocus_angle.div(2.0).to_radians().tan());
-        let defocus_disk_u = u * defocus_radius;
-        let defocus_disk_v = v * defocus_radius;
+            let defocus_radius = focus_dist * (defocus_angle.div(2.0).to_radians().tan());
+            let defocus_disk_u = u * defocus_radius;
+            let defocus_disk_v = v * defocus_radius;
 
-        let pixel_samples_scale = f64::from(samples_per_pixel).recip();
+            let focus_plane_normal = (tilt != 0.0 || swing != 0.0).then(|| {
+                let n = rotate(&w, &u, tilt.to_radians());
+                rotate(&n, &v, swing.to_radians())
+            });
 
-        Self {
+            View {
+                center,
+                u,
+                v,
+                w,
+                pixel00_loc,
+                pixel_delta_u,
+                pixel_delta_v,
+                defocus_disk_u,
+                defocus_disk_v,
+                focus_plane_normal,
+                ods_offset,
+            }
+        };
+
+        let views = match stereo {
+            None => vec![view(lookfrom, lookat, 0.0)],
+            Some(Stereo {
+                interocular,
+                convergence,
+                ..
+            }) => [-0.5, 0.5]
+                .into_iter()
+                .map(|side| {
+                    let offset = side * interocular;
+                    if projection.is_panoramic() {
+                        return view(lookfrom, lookat, offset);
+                    }
+                    let right = Vec3::cross(&vup, &(lookfrom - lookat)).unit();
+                    let eye = lookfrom + offset * right;
+                    let target = match convergence {
+                        Convergence::Parallel => lookat + offset * right,
+                        Convergence::ToeIn { distance } => {
+                            lookfrom + distance * (lookat - lookfrom).unit()
+                        }
+                    };
+                    view(eye, target, 0.0)
+                })
+                .collect(),
+        };
+
+        let (frame_width, frame_height) = match stereo.map(|stereo| stereo.layout) {
+            None => (image_width, image_height),
+            Some(StereoLayout::SideBySide) => (2 * image_width, image_height),
+            Some(StereoLayout::OverUnder) => (image_width, 2 * image_height),
+        };
+
+        let image = Tile {
+            x0: 0,
+            y0: 0,
+            x1: frame_width,
+            y1: frame_height,
+        };
+        let bounds = match crop {
+            Some(crop) => crop.bounds(frame_width, frame_height),
+            None => image,
+        };
+        // Pixels just outside a crop still splat into its border through the filter.
+        let sample_bounds = bounds.expand(film::filter_margin(&filter), &image);
+        let tiles = tile::tiles(sample_bounds, tile_size, tile_order);
+
+        Ok(Self {
+            projection,
             image_width,
             image_height,
-            center,
-            pixel00_loc,
-            pixel_delta_u,
-            pixel_delta_v,
-            defocus_disk_u,
-            defocus_disk_v,
+            views,
+            stereo,
+            lens,
+            focus_dist,
             defocus_angle,
+            aperture,
+            display,
+            spectral,
+            to_rec709,
+            output_path,
             samples_per_pixel,
-            pixel_samples_scale,
             max_depth,
-        }
+            snapshot_path,
+            bounds,
+            tiles,
+            filter,
+            seed,
+            checkpoint_path,
+            max_pass_samples,
+            resume,
+        })
     }
 
-    pub fn render(&self, world: &HittableList) {
-        let total = self.image_width * self.image_height;
+    /// Renders in progressive passes, doubling the accumulated sample count
+    /// with every pass, but adding at most `max_pass_samples` samples, until
+    /// `samples_per_pixel` is reached, then writes the final image.
+    pub fn render(&self, world: &HittableList) -> io::Result<()> {
+        let mut film = match &self.checkpoint_path {
+            Some(path) if self.resume => {
+                Film::load_checkpoint(path, self.bounds, self.seed, &self.filter)?
+            }
+            _ => Film::new(self.bounds),
+        };
+        // Tiles cover the filter margin around a crop too.
+        let total = self
+            .tiles
+            .iter()
+            .map(|tile| u64::from(tile.len()))
+            .sum::<u64>();
+        let remaining = self.samples_per_pixel.saturating_sub(film.samples());
+        let progress = ProgressBar::new(total * u64::from(remaining));
+
+        while film.samples() < self.samples_per_pixel {
+            let samples = film
+                .samples()
+                .min(self.max_pass_samples)
+                .clamp(1, self.samples_per_pixel - film.samples());
+            self.render_pass(world, &mut film, samples, &progress);
 
-        #[allow(clippy::cast_sign_loss)]
-        let colors = (0..total)
-            .into_par_iter()
-            .progress_count(u64::from(total))
-            .map(|n| {
-                let j = n.div(self.image_width);
-                let i = n.rem(self.image_width);
-                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-                for _ in 0..self.samples_per_pixel {
-                    let r = self.get_ray(i, j);
-                    pixel_color += Self::ray_color(&r, self.max_depth, world);
+            if let Some(path) = &self.snapshot_path {
+                film.save(path, &self.display)?;
+            }
+            if let Some(path) = &self.checkpoint_path {
+                film.save_checkpoint(path, self.seed, &self.filter)?;
+            }
+        }
+        progress.finish();
+
+        if let Some(path) = &self.output_path {
+            return film.save(path, &self.display);
+        }
+        let mut out = BufWriter::new(io::stdout().lock());
+        film.write(&mut out, Format::Ppm, &self.display)?;
+        out.flush()
+    }
+
+    fn render_pass(
+        &self,
+        world: &HittableList,
+        film: &mut Film,
+        samples: u32,
+        progress: &ProgressBar,
+    ) {
+        let first_sample = film.samples();
+        let mut rendered = self
+            .tiles
+            .iter()
+            .enumerate()
+            .par_bridge()
+            .map(|(k, tile)| {
+                let mut film_tile = film.tile(tile, self.filter);
+                for (i, j) in tile.pixels() {
+                    for s in first_sample..first_sample + samples {
+                        random::reseed(random::hash(&[
+                            self.seed,
+                            u64::from(i),
+                            u64::from(j),
+                            u64::from(s),
+                        ]));
+                        let offset = Self::sample_square();
+                        let color = self
+                            .get_ray(i, j, offset)
+                            .map_or(Color::default(), |(r, weight)| {
+                                weight * self.sample_color(&r, world)
+                            });
+                        film_tile.add_sample(
+                            f64::from(i) + offset.x(),
+                            f64::from(j) + offset.y(),
+                            color,
+                            &self.eye_bounds(i, j),
+                        );
+                    }
                 }
-                self.pixel_samples_scale * pixel_color
+                progress.inc(u64::from(tile.len() * samples));
+                (k, film_tile)
             })
             .collect::<Vec<_>>();
 
-        println!("P3\n{0} {1}\n255", self.image_width, self.image_height);
-        colors.iter().for_each(color::write);
+        // Neighbouring tiles overlap through the filter footprint; merging in
+        // tile order keeps the floating point sums reproducible.
+        rendered.sort_by_key(|(k, _)| *k);
+        for (_, film_tile) in &rendered {
+            film.merge(film_tile);
+        }
+        film.add_samples(samples);
+    }
+
+    /// Radiance arriving along `r`, traced in RGB or over sampled wavelengths.
+    fn sample_color(&self, r: &Ray, world: &HittableList) -> Color {
+        match &self.spectral {
+            None => Self::ray_color(r, self.max_depth, world),
+            Some(xyz_to_rgb) => {
+                let mut wavelengths = Wavelengths::sample(random::random());
+                let r = r.with_wavelength(Some(wavelengths.hero()));
+                let radiance = self.ray_spectrum(&r, &mut wavelengths, self.max_depth, world);
+                color::transform(xyz_to_rgb, &radiance.to_xyz(&wavelengths))
+            }
+        }
     }
 
     fn sample_square() -> Vec3 {
-        let mut rng = rand::thread_rng();
-        Vec3::new(rng.gen_range(-0.5..0.5), rng.gen_range(-0.5..0.5), 0.0)
+        Vec3::new(
+            random::gen_range(-0.5..0.5),
+            random::gen_range(-0.5..0.5),
+            0.0,
+        )
+    }
+
+    /// The part of the frame holding the eye image, or the cubemap face, that
+    /// pixel `(i, j)` belongs to, which its samples must not splat beyond.
+    fn eye_bounds(&self, i: u32, j: u32) -> Tile {
+        let (width, height) = (self.image_width, self.image_height);
+        let (x0, y0) = match self.stereo.map(|stereo| stereo.layout) {
+            None => (0, 0),
+            Some(StereoLayout::SideBySide) => (i / width * width, 0),
+            Some(StereoLayout::OverUnder) => (0, j / height * height),
+        };
+        let mut bounds = Tile {
+            x0,
+            y0,
+            x1: x0 + width,
+            y1: y0 + height,
+        };
+        if let Projection::Cubemap = self.projection {
+            // The last face also takes the columns left over when the width
+            // isn't a multiple of six.
+            let face_width = (width / 6).max(1);
+            let face = ((i - x0) / face_width).min(5);
+            bounds.x0 = x0 + face * face_width;
+            if face < 5 {
+                bounds.x1 = bounds.x0 + face_width;
+            }
+        }
+        bounds
     }
 
-    fn get_ray(&self, i: u32, j: u32) -> Ray {
+    /// Builds the ray for a sample at `offset` from the center of pixel
+    /// `(i, j)` of the output frame with the weight of its radiance, or `None`
+    /// where the projection doesn't cover the image.
+    fn get_ray(&self, i: u32, j: u32, offset: Vec3) -> Option<(Ray, f64)> {
+        let (eye, i, j) = match self.stereo.map(|stereo| stereo.layout) {
+            None => (0, i, j),
+            Some(StereoLayout::SideBySide) => (i / self.image_width, i % self.image_width, j),
+            Some(StereoLayout::OverUnder) => (j / self.image_height, i, j % self.image_height),
+        };
+        let view = &self.views[usize::try_from(eye).expect("eye out of range")];
         let i = f64::from(i);
         let j = f64::from(j);
-        let offset = Self::sample_square();
-        let pixel_sample = self.pixel00_loc
-            + (i + offset.x()) * self.pixel_delta_u
-            + (j + offset.y()) * self.pixel_delta_v;
+
+        let s = (i + 0.5 + offset.x()) / f64::from(self.image_width);
+        let t = (j + 0.5 + offset.y()) / f64::from(self.image_height);
+
+        if self.projection.is_panoramic() {
+            return self
+                .panorama_direction(view, s, t)
+                .map(|direction| (self.panorama_ray(view, direction), 1.0));
+        }
+        if let Some(lens) = &self.lens {
+            return Self::lens_ray(
+                view,
+                &lens.system,
+                (0.5 - s) * lens.film_width,
+                (t - 0.5) * lens.film_height,
+            );
+        }
+
+        let pixel_sample = view.pixel00_loc
+            + (i + offset.x()) * view.pixel_delta_u
+            + (j + offset.y()) * view.pixel_delta_v;
+
+        // An orthographic lens sits straight behind each pixel, on the camera plane.
+        let lens_center = match self.projection {
+            Projection::Orthographic { .. } => pixel_sample + self.focus_dist * view.w,
+            _ => view.center,
+        };
 
         let ray_origin = if self.defocus_angle <= 0.0 {
-            self.center
+            lens_center
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(view, lens_center)
+        };
+
+        let focus_point = match view.focus_plane_normal {
+            Some(normal) => self.tilted_focus_point(view, &normal, lens_center, pixel_sample),
+            None => pixel_sample,
         };
 
-        let ray_direction = pixel_sample - ray_origin;
-        Ray::new(ray_origin, ray_direction)
+        let ray_direction = focus_point - ray_origin;
+        Some((Ray::new(ray_origin, ray_direction), 1.0))
+    }
+
+    /// Where the chief ray through `pixel_sample` crosses the tilted plane of
+    /// focus, which still passes through the center of the untilted one.
+    fn tilted_focus_point(
+        &self,
+        view: &View,
+        normal: &Vec3,
+        lens_center: Point3,
+        pixel_sample: Point3,
+    ) -> Point3 {
+        let direction = pixel_sample - lens_center;
+        let denominator = Vec3::dot(&direction, normal);
+        if denominator.abs() < 1e-8 {
+            return pixel_sample;
+        }
+        let plane_point = view.center - self.focus_dist * view.w;
+        let t = Vec3::dot(&(plane_point - lens_center), normal) / denominator;
+        lens_center + t * direction
+    }
+
+    /// Traces a ray from film point `(x, y)` through `lens` towards a random
+    /// point on its rear element. The lens inverts the image, so the film point
+    /// is mirrored from the pixel. Rays are weighted by the cos⁴ falloff of
+    /// light reaching the film off axis.
+    fn lens_ray(view: &View, lens: &LensSystem, x: f64, y: f64) -> Option<(Ray, f64)> {
+        let film_point = Point3::new(x, y, 0.0);
+        let p = Vec3::random_in_unit_disk() * lens.rear_aperture_radius();
+        let rear_point = Point3::new(p.x(), p.y(), lens.rear_z());
+        let direction = (rear_point - film_point).unit();
+
+        let ray = lens.trace_from_film(&Ray::new(film_point, direction))?;
+        let weight = direction.z().powi(4);
+
+        // Camera space looks down +z, the view down -w.
+        let to_world = |c: &Vec3| c.x() * view.u + c.y() * view.v - c.z() * view.w;
+        let origin = view.center + to_world(ray.origin());
+        Some((Ray::new(origin, to_world(ray.direction())), weight))
+    }
+
+    /// Direction seen at normalized image position `(s, t)`, both in `0..1`
+    /// from the top left corner, for the panoramic projections.
+    fn panorama_direction(&self, view: &View, s: f64, t: f64) -> Option<Vec3> {
+        let (right, up, forward) = (view.u, view.v, -view.w);
+
+        match self.projection {
+            Projection::Equirectangular => {
+                let longitude = (s - 0.5) * 2.0 * PI;
+                let latitude = (0.5 - t) * PI;
+                let horizontal = longitude.sin() * right + longitude.cos() * forward;
+                Some(latitude.cos() * horizontal + latitude.sin() * up)
+            }
+            Projection::Cubemap => {
+                let s = s * 6.0;
+                let face = s.floor().min(5.0);
+                // Face coordinates in -1..1, `b` pointing down the image.
+                let a = 2.0 * (s - face) - 1.0;
+                let b = 2.0 * t - 1.0;
+                #[allow(clippy::cast_possible_truncation)]
+                #[allow(clippy::cast_sign_loss)]
+                let direction = match face as u32 {
+                    0 => right - a * forward - b * up,
+                    1 => -right + a * forward - b * up,
+                    2 => up + a * right + b * forward,
+                    3 => -up + a * right - b * forward,
+                    4 => forward + a * right - b * up,
+                    _ => -forward - a * right - b * up,
+                };
+                Some(direction)
+            }
+            Projection::Fisheye { fov, mapping } => {
+                let width = f64::from(self.image_width);
+                let height = f64::from(self.image_height);
+                let radius = width.min(height) / 2.0;
+                let x = (s - 0.5) * width / radius;
+                let y = (t - 0.5) * height / radius;
+                let r = x.hypot(y);
+                if r > 1.0 {
+                    return None;
+                }
+
+                let half_fov = fov.to_radians() / 2.0;
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * half_fov,
+                    FisheyeMapping::Equisolid => 2.0 * (r * (half_fov / 2.0).sin()).asin(),
+                };
+                let phi = y.atan2(x);
+                let radial = phi.cos() * right - phi.sin() * up;
+                Some(theta.cos() * forward + theta.sin() * radial)
+            }
+            _ => None,
+        }
+    }
+
+    /// Ray leaving `view` in `direction`. In stereo, the eye sits off center
+    /// perpendicular to the horizontal part of the direction, and toe-in
+    /// turns the ray towards the convergence sphere.
+    fn panorama_ray(&self, view: &View, direction: Vec3) -> Ray {
+        if view.ods_offset == 0.0 {
+            return Ray::new(view.center, direction);
+        }
+
+        let horizontal = direction - Vec3::dot(&direction, &view.v) * view.v;
+        let sideways = Vec3::cross(&horizontal, &view.v);
+        if sideways.near_zero() {
+            return Ray::new(view.center, direction);
+        }
+        let origin = view.center + view.ods_offset * sideways.unit();
+
+        match self.stereo.map(|stereo| stereo.convergence) {
+            Some(Convergence::ToeIn { distance }) => {
+                let target = view.center + distance * direction.unit();
+                Ray::new(origin, target - origin)
+            }
+            _ => Ray::new(origin, direction),
+        }
     }
 
     fn ray_color(r: &Ray, depth: u32, world: &HittableList) -> Color {
         if depth == 0 {
             return Color::default();
         }
-        let interval = Interval::new(0.001, f64::INFINITY);
-        if let Some(hit_record) = world.hit(r, &interval) {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let Some((r, hit, transmittance)) = Self::walk_medium(r, world, white, |weight| weight)
+        else {
+            return Color::default();
+        };
+
+        if let Some(hit_record) = hit {
             if let Some(ScatterResult {
                 attenuation,
                 scattered,
-            }) = hit_record.mat.scatter(r, &hit_record)
+            }) = hit_record.mat.scatter(&r, &hit_record)
             {
-                return attenuation * Self::ray_color(&scattered, depth - 1, world);
+                return transmittance * attenuation * Self::ray_color(&scattered, depth - 1, world);
             }
             return Color::default();
         }
         let unit_direction = r.direction().unit();
         let a = 0.5 * (unit_direction.y() + 1.0);
-        (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+        transmittance * ((1.0 - a) * white + a * Color::new(0.5, 0.7, 1.0))
+    }
+
+    /// The spectral counterpart of `ray_color`. The ray carries the hero
+    /// wavelength, which is all that dispersive materials refract.
+    fn ray_spectrum(
+        &self,
+        r: &Ray,
+        wavelengths: &mut Wavelengths,
+        depth: u32,
+        world: &HittableList,
+    ) -> Spectrum {
+        if depth == 0 {
+            return Spectrum::default();
+        }
+        let upsample = |c: &Color, wavelengths: &Wavelengths| {
+            Spectrum::from_rgb(&color::transform(&self.to_rec709, c), wavelengths)
+        };
+        let one = Spectrum::sample(wavelengths, |_| 1.0);
+        let Some((r, hit, transmittance)) =
+            Self::walk_medium(r, world, one, |weight| upsample(&weight, wavelengths))
+        else {
+            return Spectrum::default();
+        };
+
+        if let Some(hit_record) = hit {
+            if let Some(ScatterResult {
+                attenuation,
+                scattered,
+            }) = hit_record.mat.scatter(&r, &hit_record)
+            {
+                if hit_record.mat.is_dispersive() {
+                    wavelengths.terminate_secondary();
+                }
+                let attenuation = transmittance * upsample(&attenuation, wavelengths);
+                return attenuation * self.ray_spectrum(&scattered, wavelengths, depth - 1, world);
+            }
+            return Spectrum::default();
+        }
+        let unit_direction = r.direction().unit();
+        let a = 0.5 * (unit_direction.y() + 1.0);
+        let sky = (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0);
+        transmittance * upsample(&sky, wavelengths)
+    }
+
+    /// Follows `r` through the medium it travels in, if any, from one
+    /// scattering event to the next until it reaches a surface or escapes.
+    /// Returns the last ray of the walk with what it hits, and the product
+    /// of the event weights, each converted by `weight`; `None` when the
+    /// walk runs past `medium::MAX_EVENTS`.
+    ///
+    /// Dense media scatter thousands of times, so the walk neither recurses
+    /// nor counts towards `max_depth`.
+    fn walk_medium<T: Mul<Output = T>>(
+        r: &Ray,
+        world: &HittableList,
+        one: T,
+        weight: impl Fn(Color) -> T,
+    ) -> Option<(Ray, Option<HitRecord>, T)> {
+        let interval = Interval::new(0.001, f64::INFINITY);
+        let mut r = *r;
+        let mut transmittance = one;
+        for _ in 0..medium::MAX_EVENTS {
+            let hit = world.hit(&r, &interval);
+            let Some(medium) = r.medium() else {
+                return Some((r, hit, transmittance));
+            };
+            let t_max = hit.as_ref().map_or(f64::INFINITY, |hit| hit.t);
+            let (scattered, event) = medium.interact(&r, t_max);
+            transmittance = transmittance * weight(event);
+            match scattered {
+                Some(scattered) => r = scattered,
+                None => return Some((r, hit, transmittance)),
+            }
+        }
+        None
+    }
+
+    fn defocus_disk_sample(&self, view: &View, lens_center: Point3) -> Point3 {
+        let p = self.aperture.sample();
+        lens_center + p.x() * view.defocus_disk_u + p.y() * view.defocus_disk_v
+    }
+}
+
+/// Rotates `v` by `angle` radians about the unit `axis` (Rodrigues' formula).
+fn rotate(v: &Vec3, axis: &Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    cos * v + sin * Vec3::cross(axis, v) + (1.0 - cos) * Vec3::dot(axis, v) * axis
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+    use crate::{material::Material, sphere::Sphere};
+
+    fn scene() -> HittableList {
+        let mut world = HittableList::default();
+        let ground = Arc::new(Material::lambertian(Color::new(0.5, 0.5, 0.5)));
+        world.add(Box::new(Sphere::new(
+            Point3::new(0.0, -100.5, -1.0),
+            100.0,
+            ground,
+        )));
+        let glass = Arc::new(Material::dielectric(1.5));
+        world.add(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, -1.0),
+            0.5,
+            glass,
+        )));
+        world
+    }
+
+    fn settings(
+        samples_per_pixel: u32,
+        resume: bool,
+        checkpoint: &Path,
+        output: &Path,
+    ) -> Settings {
+        Settings {
+            aspect_ratio: 1.0,
+            image_width: 24,
+            samples_per_pixel,
+            max_depth: 8,
+            tile_size: 8,
+            filter: Filter::Gaussian {
+                radius: 1.5,
+                alpha: 2.0,
+            },
+            checkpoint_path: Some(checkpoint.to_path_buf()),
+            max_pass_samples: 3,
+            resume,
+            output_path: Some(output.to_path_buf()),
+            ..Settings::default()
+        }
+    }
+
+    fn render(samples_per_pixel: u32, resume: bool, checkpoint: &Path, output: &Path) {
+        let camera = Camera::new(settings(samples_per_pixel, resume, checkpoint, output)).unwrap();
+        camera.render(&scene()).expect("render failed");
     }
 
-    fn defocus_disk_sample(&self) -> Point3 {
-        let p = Vec3::random_in_unit_disk();
-        self.center + p.x() * self.defocus_disk_u + p.y() * self.defocus_disk_v
+    #[test]
+    fn resumed_render_matches_uninterrupted_render() {
+        let dir = std::env::temp_dir().join(format!("ray-tracing-resume-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let checkpoint = dir.join("checkpoint.bin");
+        let straight = dir.join("straight.exr");
+        let resumed = dir.join("resumed.exr");
+
+        render(16, false, &checkpoint, &straight);
+        for interrupted_at in [4, 5] {
+            render(interrupted_at, false, &checkpoint, &resumed);
+            render(16, true, &checkpoint, &resumed);
+            assert_eq!(
+                fs::read(&straight).unwrap(),
+                fs::read(&resumed).unwrap(),
+                "resuming after {interrupted_at} samples changed the image"
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resuming_with_another_filter_fails() {
+        let dir = std::env::temp_dir().join(format!("ray-tracing-filter-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let checkpoint = dir.join("checkpoint.bin");
+        let output = dir.join("image.exr");
+
+        render(2, false, &checkpoint, &output);
+        let camera = Camera::new(Settings {
+            filter: Filter::default(),
+            ..settings(4, true, &checkpoint, &output)
+        })
+        .unwrap();
+        let error = camera.render(&scene()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn focusing_closer_than_the_lens_can_is_an_error() {
+        let lens = LensSystem::load(Path::new("lenses/dgauss.50mm.dat")).unwrap();
+        let settings = |focus_dist| Settings {
+            focus_dist,
+            projection: Projection::Realistic {
+                lens: Arc::new(lens.clone()),
+                film_diagonal: 35.0,
+            },
+            ..Settings::default()
+        };
+        assert!(Camera::new(settings(10.0)).is_ok());
+        let error = Camera::new(settings(0.01)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn realistic_lens_rejects_thin_lens_settings() {
+        let lens = LensSystem::load(Path::new("lenses/dgauss.50mm.dat")).unwrap();
+        let realistic = || Settings {
+            projection: Projection::Realistic {
+                lens: Arc::new(lens.clone()),
+                film_diagonal: 35.0,
+            },
+            ..Settings::default()
+        };
+        assert!(Camera::new(realistic()).is_ok());
+        for settings in [
+            Settings {
+                lens_shift: (0.1, 0.0),
+                ..realistic()
+            },
+            Settings {
+                focus_tilt: (0.0, 5.0),
+                ..realistic()
+            },
+            Settings {
+                physical_camera: Some(PhysicalCamera::default()),
+                ..realistic()
+            },
+        ] {
+            let error = Camera::new(settings).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn samples_stay_inside_their_cubemap_face() {
+        let camera = |stereo| {
+            Camera::new(Settings {
+                aspect_ratio: 6.0,
+                image_width: 12,
+                projection: Projection::Cubemap,
+                stereo,
+                ..Settings::default()
+            })
+            .unwrap()
+        };
+        let faces = |camera: &Camera, i| {
+            let bounds = camera.eye_bounds(i, 0);
+            (bounds.x0, bounds.x1)
+        };
+
+        let mono = camera(None);
+        assert_eq!(faces(&mono, 1), (0, 2));
+        assert_eq!(faces(&mono, 2), (2, 4));
+        assert_eq!(faces(&mono, 11), (10, 12));
+
+        let stereo = camera(Some(Stereo {
+            interocular: 0.065,
+            convergence: Convergence::Parallel,
+            layout: StereoLayout::SideBySide,
+        }));
+        assert_eq!(faces(&stereo, 11), (10, 12));
+        assert_eq!(faces(&stereo, 12), (12, 14));
+        assert_eq!(faces(&stereo, 15), (14, 16));
+    }
+
+    #[test]
+    fn dense_media_walk_past_max_depth() {
+        // Without absorption all light entering the sphere leaves it again,
+        // after about a thousand scattering events.
+        let mut world = HittableList::default();
+        let white = Color::new(1.0, 1.0, 1.0);
+        let mat = Material::subsurface(1.5, white, 0.03 * white);
+        world.add(Box::new(Sphere::new(Point3::default(), 1.0, Arc::new(mat))));
+
+        let r = Ray::new(Point3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        let samples = 200;
+        let lost = (0..samples)
+            .filter(|&k| {
+                random::reseed(k);
+                Camera::ray_color(&r, 10, &world).near_zero()
+            })
+            .count();
+        assert!(lost < 5, "{lost} of {samples} paths lost");
     }
 }
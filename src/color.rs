@@ -1,17 +1,216 @@
-use crate::{interval::Interval, vec3::Vec3};
+use std::io::{self, Write};
+
+use crate::{interval::Interval, tonemap::ToneMap, vec3::Vec3};
 
 pub type Color = Vec3;
 
-fn linear_to_gamma(linear_component: f64) -> f64 {
-    if linear_component > 0.0 {
-        f64::sqrt(linear_component)
-    } else {
+pub type Matrix = [[f64; 3]; 3];
+
+/// An RGB color space, defined by its primaries and white point.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[allow(unused)]
+pub enum ColorSpace {
+    /// sRGB / Rec. 709 primaries, D65 white.
+    #[default]
+    Srgb,
+    /// ACES AP1 primaries, ACES (≈D60) white; the usual rendering space of
+    /// ACES pipelines.
+    AcesCg,
+    /// DCI-P3 primaries, D65 white.
+    DisplayP3,
+}
+
+impl ColorSpace {
+    /// Chromaticities of the red, green and blue primaries and of the white
+    /// point, as `(x, y)` pairs.
+    pub fn chromaticities(self) -> [(f64, f64); 4] {
+        match self {
+            Self::Srgb => [(0.64, 0.33), (0.30, 0.60), (0.15, 0.06), (0.3127, 0.3290)],
+            Self::AcesCg => [
+                (0.713, 0.293),
+                (0.165, 0.830),
+                (0.128, 0.044),
+                (0.32168, 0.33767),
+            ],
+            Self::DisplayP3 => [
+                (0.680, 0.320),
+                (0.265, 0.690),
+                (0.150, 0.060),
+                (0.3127, 0.3290),
+            ],
+        }
+    }
+
+    fn rgb_to_xyz(self) -> Matrix {
+        match self {
+            Self::Srgb => [
+                [0.412_456_4, 0.357_576_1, 0.180_437_5],
+                [0.212_672_9, 0.715_152_2, 0.072_175_0],
+                [0.019_333_9, 0.119_192_0, 0.950_304_1],
+            ],
+            Self::AcesCg => [
+                [0.662_454_181_1, 0.134_004_206_5, 0.156_187_687_0],
+                [0.272_228_716_8, 0.674_081_765_8, 0.053_689_517_4],
+                [-0.005_574_649_5, 0.004_060_733_5, 1.010_339_100_3],
+            ],
+            Self::DisplayP3 => [
+                [0.486_570_9, 0.265_667_7, 0.198_217_3],
+                [0.228_974_6, 0.691_738_5, 0.079_286_9],
+                [0.000_000_0, 0.045_113_4, 1.043_944_4],
+            ],
+        }
+    }
+
+    /// Matrix taking CIE XYZ to linear RGB in this space.
+    pub fn xyz_to_rgb(self) -> Matrix {
+        match self {
+            Self::Srgb => [
+                [3.240_454_2, -1.537_138_5, -0.498_531_4],
+                [-0.969_266_0, 1.876_010_8, 0.041_556_0],
+                [0.055_643_4, -0.204_025_9, 1.057_225_2],
+            ],
+            Self::AcesCg => [
+                [1.641_023_379_7, -0.324_803_294_2, -0.236_424_695_2],
+                [-0.663_662_858_7, 1.615_331_591_7, 0.016_756_347_7],
+                [0.011_721_894_3, -0.008_284_442_0, 0.988_394_858_5],
+            ],
+            Self::DisplayP3 => [
+                [2.493_496_9, -0.931_383_6, -0.402_710_8],
+                [-0.829_489_0, 1.762_664_1, 0.023_624_7],
+                [0.035_845_8, -0.076_172_4, 0.956_884_5],
+            ],
+        }
+    }
+
+    fn white(self) -> Color {
+        let (x, y) = self.chromaticities()[3];
+        Color::new(x / y, 1.0, (1.0 - x - y) / y)
+    }
+
+    /// Matrix taking linear colors in `self` to linear colors in `target`,
+    /// adapting the white point with the Bradford transform.
+    pub fn conversion(self, target: Self) -> Matrix {
+        const BRADFORD: Matrix = [
+            [0.8951, 0.2664, -0.1614],
+            [-0.7502, 1.7135, 0.0367],
+            [0.0389, -0.0685, 1.0296],
+        ];
+        const BRADFORD_INVERSE: Matrix = [
+            [0.986_992_9, -0.147_054_3, 0.159_962_7],
+            [0.432_305_3, 0.518_360_3, 0.049_291_2],
+            [-0.008_528_7, 0.040_042_8, 0.968_486_7],
+        ];
+
+        let source_cone = transform(&BRADFORD, &self.white());
+        let target_cone = transform(&BRADFORD, &target.white());
+        let gain = [
+            [target_cone.x() / source_cone.x(), 0.0, 0.0],
+            [0.0, target_cone.y() / source_cone.y(), 0.0],
+            [0.0, 0.0, target_cone.z() / source_cone.z()],
+        ];
+        let adaptation = multiply(&BRADFORD_INVERSE, &multiply(&gain, &BRADFORD));
+        multiply(
+            &target.xyz_to_rgb(),
+            &multiply(&adaptation, &self.rgb_to_xyz()),
+        )
+    }
+}
+
+/// Turns accumulated linear radiance into displayable linear color, before
+/// the output encoding.
+#[derive(Clone, Copy, Debug)]
+pub struct Display {
+    /// Linear scale applied to the radiance before tone mapping.
+    pub exposure: f64,
+    pub tone_map: ToneMap,
+    /// Color space of the written image.
+    pub space: ColorSpace,
+    /// Converts from the working space the scene is rendered in to `space`.
+    conversion: Matrix,
+    /// Converts from the working space to linear Rec. 709, which the tone
+    /// curves are fitted to.
+    to_rec709: Matrix,
+    /// Converts tone mapped Rec. 709 to `space`.
+    from_rec709: Matrix,
+}
+
+impl Display {
+    pub fn new(
+        exposure: f64,
+        tone_map: ToneMap,
+        working_space: ColorSpace,
+        space: ColorSpace,
+    ) -> Self {
+        Self {
+            exposure,
+            tone_map,
+            space,
+            conversion: working_space.conversion(space),
+            to_rec709: working_space.conversion(ColorSpace::Srgb),
+            from_rec709: ColorSpace::Srgb.conversion(space),
+        }
+    }
+
+    /// Scene-linear color in the output space, for high dynamic range output.
+    pub fn linear(&self, c: &Color) -> Color {
+        transform(&self.conversion, &(self.exposure * c))
+    }
+
+    /// Tone mapped linear color in the output space.
+    pub fn apply(&self, c: &Color) -> Color {
+        let rec709 = transform(&self.to_rec709, &(self.exposure * c));
+        transform(&self.from_rec709, &self.tone_map.apply(rec709))
+    }
+}
+
+/// Relative luminance of a linear Rec. 709 color.
+pub fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+/// Multiplies `c` by the row-major matrix `m`.
+pub fn transform(m: &Matrix, c: &Color) -> Color {
+    let row = |r: &[f64; 3]| r[0] * c.x() + r[1] * c.y() + r[2] * c.z();
+    Color::new(row(&m[0]), row(&m[1]), row(&m[2]))
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+/// The piecewise sRGB transfer function, also used by Display P3.
+fn linear_to_srgb(linear_component: f64) -> f64 {
+    if linear_component <= 0.0 {
         0.0
+    } else if linear_component <= 0.003_130_8 {
+        12.92 * linear_component
+    } else {
+        1.055 * linear_component.powf(1.0 / 2.4) - 0.055
     }
 }
 
+/// Decodes sRGB-encoded values to linear ones.
+pub fn srgb_to_linear(c: &Color) -> Color {
+    let decode = |v: f64| {
+        if v <= 0.040_45 {
+            v / 12.92
+        } else {
+            ((v + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    Color::new(decode(c.x()), decode(c.y()), decode(c.z()))
+}
+
+/// Encodes a displayable linear color as 8-bit sRGB-encoded values.
 #[allow(clippy::cast_possible_truncation)]
-pub fn write(pixel_color: &Color) {
+#[allow(clippy::cast_sign_loss)]
+pub fn to_bytes(pixel_color: &Color) -> [u8; 3] {
     static INTENSITY: Interval = Interval {
         min: 0.0,
         max: 0.999,
@@ -21,13 +220,18 @@ pub fn write(pixel_color: &Color) {
     let g = pixel_color.y();
     let b = pixel_color.z();
 
-    let r = linear_to_gamma(r);
-    let g = linear_to_gamma(g);
-    let b = linear_to_gamma(b);
+    let r = linear_to_srgb(r);
+    let g = linear_to_srgb(g);
+    let b = linear_to_srgb(b);
 
-    let r = (256.0 * INTENSITY.clamp(r)) as i32;
-    let g = (256.0 * INTENSITY.clamp(g)) as i32;
-    let b = (256.0 * INTENSITY.clamp(b)) as i32;
+    let r = (256.0 * INTENSITY.clamp(r)) as u8;
+    let g = (256.0 * INTENSITY.clamp(g)) as u8;
+    let b = (256.0 * INTENSITY.clamp(b)) as u8;
+
+    [r, g, b]
+}
 
-    println!("{r} {g} {b}");
+pub fn write(out: &mut impl Write, pixel_color: &Color) -> io::Result<()> {
+    let [r, g, b] = to_bytes(pixel_color);
+    writeln!(out, "{r} {g} {b}")
 }
@@ -0,0 +1,155 @@
+use std::io::{self, Write};
+
+use crate::{color::ColorSpace, vec3::Vec3};
+
+/// Writes linear RGB `pixels`, row by row, as an uncompressed 32-bit float
+/// OpenEXR image carrying the chromaticities of `space`.
+pub fn write(
+    out: &mut impl Write,
+    width: u32,
+    height: u32,
+    pixels: &[Vec3],
+    space: ColorSpace,
+) -> io::Result<()> {
+    let too_large = || io::Error::other("image too large for OpenEXR");
+    let max_x = i32::try_from(width).map_err(|_| too_large())? - 1;
+    let max_y = i32::try_from(height).map_err(|_| too_large())? - 1;
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]);
+    // Version 2, single part scanline image.
+    header.extend_from_slice(&2_u32.to_le_bytes());
+
+    // Channels must be listed alphabetically; pixel type 2 is 32-bit float.
+    let mut channels = Vec::new();
+    for name in [b"B", b"G", b"R"] {
+        channels.extend_from_slice(name);
+        channels.push(0);
+        channels.extend_from_slice(&2_i32.to_le_bytes());
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        channels.extend_from_slice(&1_i32.to_le_bytes());
+        channels.extend_from_slice(&1_i32.to_le_bytes());
+    }
+    channels.push(0);
+    attribute(&mut header, "channels", "chlist", &channels);
+
+    let mut chromaticities = Vec::new();
+    for (x, y) in space.chromaticities() {
+        #[allow(clippy::cast_possible_truncation)]
+        for v in [x as f32, y as f32] {
+            chromaticities.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+    attribute(
+        &mut header,
+        "chromaticities",
+        "chromaticities",
+        &chromaticities,
+    );
+    attribute(&mut header, "compression", "compression", &[0]);
+
+    let mut window = Vec::new();
+    for v in [0, 0, max_x, max_y] {
+        window.extend_from_slice(&v.to_le_bytes());
+    }
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1.0_f32.to_le_bytes(),
+    );
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1.0_f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    // Without compression every block is one scanline.
+    let line_size = width.checked_mul(3 * 4).ok_or_else(too_large)?;
+    let block_size = 8 + u64::from(line_size);
+    let table_end = header.len() as u64 + 8 * u64::from(height);
+    out.write_all(&header)?;
+    for y in 0..u64::from(height) {
+        out.write_all(&(table_end + y * block_size).to_le_bytes())?;
+    }
+
+    let width = usize::try_from(width).map_err(|_| too_large())?;
+    for (y, row) in (0_i32..).zip(pixels.chunks(width.max(1))) {
+        out.write_all(&i32::to_le_bytes(y))?;
+        out.write_all(&line_size.to_le_bytes())?;
+        for channel in [2, 1, 0] {
+            for pixel in row {
+                #[allow(clippy::cast_possible_truncation)]
+                out.write_all(&(pixel.at(channel) as f32).to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    let len = u32::try_from(value.len()).expect("attribute fits in 32 bits");
+    header.extend_from_slice(&len.to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Length of the header of a single part scanline image: the magic
+    /// number, the version and the attributes up to their terminating zero.
+    fn header_len(exr: &[u8]) -> usize {
+        assert_eq!(&exr[..4], [0x76, 0x2f, 0x31, 0x01]);
+        let mut at = 8;
+        while exr[at] != 0 {
+            for _ in 0..2 {
+                at += exr[at..].iter().position(|&b| b == 0).unwrap() + 1;
+            }
+            let size = u32::from_le_bytes(exr[at..at + 4].try_into().unwrap());
+            at += 4 + size as usize;
+        }
+        at + 1
+    }
+
+    #[test]
+    fn offsets_point_at_scanline_blocks() {
+        let (width, height) = (5_u32, 3_u32);
+        let pixels: Vec<Vec3> = (0..width * height)
+            .map(|k| Vec3::new(f64::from(k), 0.5, -1.0))
+            .collect();
+        let mut exr = Vec::new();
+        write(&mut exr, width, height, &pixels, ColorSpace::AcesCg).unwrap();
+
+        let table = header_len(&exr);
+        let line_size = 12 * width;
+        let mut expected = table + 8 * height as usize;
+        for y in 0..height {
+            let entry = table + 8 * y as usize;
+            let offset = u64::from_le_bytes(exr[entry..entry + 8].try_into().unwrap());
+            assert_eq!(offset as usize, expected, "scanline {y}");
+            let block = &exr[expected..];
+            assert_eq!(i32::from_le_bytes(block[..4].try_into().unwrap()), y as i32);
+            assert_eq!(
+                u32::from_le_bytes(block[4..8].try_into().unwrap()),
+                line_size
+            );
+            // Channels are stored B, G, R; red holds the pixel index.
+            let red = &block[8 + 8 * width as usize..];
+            let first = f32::from_le_bytes(red[..4].try_into().unwrap());
+            assert_eq!(first, (y * width) as f32);
+            expected += 8 + line_size as usize;
+        }
+        assert_eq!(exr.len(), expected);
+    }
+}
@@ -0,0 +1,330 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    color::{self, Color, Display},
+    exr,
+    filter::Filter,
+    png,
+    tile::Tile,
+};
+
+/// Image file formats the film can be written in.
+#[derive(Clone, Copy, Debug)]
+pub enum Format {
+    Ppm,
+    Png,
+    Exr,
+}
+
+impl Format {
+    /// Picks the format from the extension of `path`, defaulting to PPM.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("png") => Self::Png,
+            Some(ext) if ext.eq_ignore_ascii_case("exr") => Self::Exr,
+            _ => Self::Ppm,
+        }
+    }
+}
+
+const CHECKPOINT_MAGIC: &[u8; 8] = b"RTCKPT01";
+
+/// Filter-weighted radiance and the sum of the weights that produced it.
+#[derive(Clone, Copy, Default)]
+struct Pixel {
+    color: Color,
+    weight: f64,
+}
+
+impl Pixel {
+    fn color(&self) -> Color {
+        if self.weight > 0.0 {
+            self.color / self.weight
+        } else {
+            Color::default()
+        }
+    }
+}
+
+/// Accumulated radiance for every pixel of the rendered region, shared by all
+/// render passes.
+pub struct Film {
+    bounds: Tile,
+    pixels: Vec<Pixel>,
+    samples: u32,
+}
+
+/// Samples splatted by one render tile, covering the tile plus the filter
+/// footprint around it.
+pub struct FilmTile {
+    bounds: Tile,
+    pixels: Vec<Pixel>,
+    filter: Filter,
+}
+
+impl FilmTile {
+    /// Splats `color`, sampled at continuous pixel position `(x, y)`, into
+    /// every pixel of `within` whose center lies within the filter radius.
+    pub fn add_sample(&mut self, x: f64, y: f64, color: Color, within: &Tile) {
+        let radius = self.filter.radius();
+        #[allow(clippy::cast_possible_truncation)]
+        let (i0, i1) = ((x - radius).ceil() as i64, (x + radius).floor() as i64);
+        #[allow(clippy::cast_possible_truncation)]
+        let (j0, j1) = ((y - radius).ceil() as i64, (y + radius).floor() as i64);
+
+        let bounds = self.bounds.intersect(within);
+        let i0 = i0.max(i64::from(bounds.x0));
+        let j0 = j0.max(i64::from(bounds.y0));
+        let i1 = i1.min(i64::from(bounds.x1) - 1);
+        let j1 = j1.min(i64::from(bounds.y1) - 1);
+
+        for j in j0..=j1 {
+            for i in i0..=i1 {
+                #[allow(clippy::cast_precision_loss)]
+                let weight = self.filter.eval(x - i as f64, y - j as f64);
+                if weight == 0.0 {
+                    continue;
+                }
+                let n = (j - i64::from(self.bounds.y0)) * i64::from(self.bounds.width())
+                    + (i - i64::from(self.bounds.x0));
+                let pixel = &mut self.pixels[usize::try_from(n).expect("pixel out of range")];
+                pixel.color += weight * color;
+                pixel.weight += weight;
+            }
+        }
+    }
+}
+
+impl Film {
+    /// Creates an empty film covering `bounds` of the full image.
+    pub fn new(bounds: Tile) -> Self {
+        let len = usize::try_from(bounds.len()).expect("image too large");
+        Self {
+            bounds,
+            pixels: vec![Pixel::default(); len],
+            samples: 0,
+        }
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// Creates the splat buffer for rendering `tile`: the pixels of this film
+    /// that samples inside the tile can reach through `filter`.
+    pub fn tile(&self, tile: &Tile, filter: Filter) -> FilmTile {
+        let bounds = tile
+            .expand(filter_margin(&filter), &self.bounds)
+            .intersect(&self.bounds);
+        let len = usize::try_from(bounds.len()).expect("tile too large");
+        FilmTile {
+            bounds,
+            pixels: vec![Pixel::default(); len],
+            filter,
+        }
+    }
+
+    /// Adds the samples splatted into `tile`.
+    pub fn merge(&mut self, tile: &FilmTile) {
+        for ((i, j), splat) in tile.bounds.pixels().zip(&tile.pixels) {
+            let n = (j - self.bounds.y0) * self.bounds.width() + (i - self.bounds.x0);
+            let pixel = &mut self.pixels[usize::try_from(n).expect("pixel out of range")];
+            pixel.color += splat.color;
+            pixel.weight += splat.weight;
+        }
+    }
+
+    /// Records that every pixel received `samples` more samples.
+    pub fn add_samples(&mut self, samples: u32) {
+        self.samples += samples;
+    }
+
+    /// Writes the image in `format`, mapping the radiance through `display`.
+    /// PPM and PNG are tone mapped and sRGB encoded; OpenEXR keeps the
+    /// exposed scene-linear values.
+    pub fn write(&self, out: &mut impl Write, format: Format, display: &Display) -> io::Result<()> {
+        let (width, height) = (self.bounds.width(), self.bounds.height());
+        match format {
+            Format::Ppm => {
+                writeln!(out, "P3\n{width} {height}\n255")?;
+                for pixel in &self.pixels {
+                    color::write(out, &display.apply(&pixel.color()))?;
+                }
+                Ok(())
+            }
+            Format::Png => {
+                let pixels = self
+                    .pixels
+                    .iter()
+                    .map(|pixel| color::to_bytes(&display.apply(&pixel.color())))
+                    .collect::<Vec<_>>();
+                png::write(out, width, height, &pixels, display.space)
+            }
+            Format::Exr => {
+                let pixels = self
+                    .pixels
+                    .iter()
+                    .map(|pixel| display.linear(&pixel.color()))
+                    .collect::<Vec<_>>();
+                exr::write(out, width, height, &pixels, display.space)
+            }
+        }
+    }
+
+    /// Writes the image to `path`, in the format its extension names.
+    pub fn save(&self, path: &Path, display: &Display) -> io::Result<()> {
+        let format = Format::from_path(path);
+        write_atomically(path, |out| self.write(out, format, display))
+    }
+
+    /// Persists the raw accumulated radiance, weights and sample count, tagged with the
+    /// render `seed` and the `filter` that weighted the samples, so a later run can keep
+    /// adding samples to it.
+    pub fn save_checkpoint(&self, path: &Path, seed: u64, filter: &Filter) -> io::Result<()> {
+        write_atomically(path, |out| {
+            out.write_all(CHECKPOINT_MAGIC)?;
+            out.write_all(&seed.to_le_bytes())?;
+            let (kind, parameters) = filter.parameters();
+            out.write_all(&kind.to_le_bytes())?;
+            for p in parameters {
+                out.write_all(&p.to_le_bytes())?;
+            }
+            let Tile { x0, y0, x1, y1 } = self.bounds;
+            for n in [x0, y0, x1, y1, self.samples] {
+                out.write_all(&n.to_le_bytes())?;
+            }
+            for Pixel { color, weight } in &self.pixels {
+                for c in [color.x(), color.y(), color.z(), *weight] {
+                    out.write_all(&c.to_le_bytes())?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Reads a checkpoint written by [`Film::save_checkpoint`], refusing it if
+    /// it belongs to a render of a different region, seed or filter.
+    pub fn load_checkpoint(
+        path: &Path,
+        bounds: Tile,
+        seed: u64,
+        filter: &Filter,
+    ) -> io::Result<Self> {
+        let mut input = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != CHECKPOINT_MAGIC {
+            return Err(invalid_checkpoint("not a render checkpoint"));
+        }
+        if read_u64(&mut input)? != seed {
+            return Err(invalid_checkpoint(
+                "checkpoint was rendered with another seed",
+            ));
+        }
+        let kind = read_u32(&mut input)?;
+        let parameters = [
+            read_f64(&mut input)?,
+            read_f64(&mut input)?,
+            read_f64(&mut input)?,
+        ];
+        if (kind, parameters) != filter.parameters() {
+            return Err(invalid_checkpoint(
+                "checkpoint was rendered with another filter",
+            ));
+        }
+        let stored = [
+            read_u32(&mut input)?,
+            read_u32(&mut input)?,
+            read_u32(&mut input)?,
+            read_u32(&mut input)?,
+        ];
+        if stored != [bounds.x0, bounds.y0, bounds.x1, bounds.y1] {
+            return Err(invalid_checkpoint("checkpoint covers another image region"));
+        }
+
+        let mut film = Self::new(bounds);
+        film.samples = read_u32(&mut input)?;
+        for pixel in &mut film.pixels {
+            let x = read_f64(&mut input)?;
+            let y = read_f64(&mut input)?;
+            let z = read_f64(&mut input)?;
+            *pixel = Pixel {
+                color: Color::new(x, y, z),
+                weight: read_f64(&mut input)?,
+            };
+        }
+        Ok(film)
+    }
+}
+
+/// Number of pixels beyond a tile that samples inside it can splat into.
+pub fn filter_margin(filter: &Filter) -> u32 {
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    let margin = (filter.radius() - 0.5).ceil().max(0.0) as u32;
+    margin
+}
+
+/// Writes `path` through a temporary file, so an interrupted write never
+/// leaves a truncated file behind.
+fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    {
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        write(&mut out)?;
+        out.flush()?;
+    }
+    fs::rename(tmp, path)
+}
+
+fn invalid_checkpoint(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64(input: &mut impl Read) -> io::Result<f64> {
+    read_u64(input).map(f64::from_bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_stay_inside_their_eye() {
+        let frame = Tile {
+            x0: 0,
+            y0: 0,
+            x1: 4,
+            y1: 1,
+        };
+        let left_eye = Tile { x1: 2, ..frame };
+        let mut film = Film::new(frame);
+        let mut tile = film.tile(&frame, Filter::Tent { radius: 2.0 });
+        tile.add_sample(1.9, 0.5, Color::new(1.0, 1.0, 1.0), &left_eye);
+        film.merge(&tile);
+
+        let weights: Vec<_> = film.pixels.iter().map(|pixel| pixel.weight).collect();
+        assert!(weights[0] > 0.0 && weights[1] > 0.0);
+        assert_eq!(weights[2..], [0.0, 0.0]);
+    }
+}
@@ -0,0 +1,104 @@
+use std::f64::consts::PI;
+
+/// Pixel reconstruction filter used to weight each sample into the pixels
+/// around it. Offsets and radii are in pixels.
+#[derive(Clone, Copy, Debug)]
+#[allow(unused)]
+pub enum Filter {
+    /// Plain average of the samples falling inside the pixel.
+    Box { radius: f64 },
+    /// Linear falloff to zero at `radius`.
+    Tent { radius: f64 },
+    /// Gaussian of falloff rate `alpha`, shifted to reach zero at `radius`.
+    Gaussian { radius: f64, alpha: f64 },
+    /// Mitchell–Netravali cubic; `b = c = 1/3` is the recommended pair.
+    Mitchell { radius: f64, b: f64, c: f64 },
+    /// Sinc windowed by a wider sinc that vanishes at `radius`.
+    Lanczos { radius: f64 },
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::Box { radius: 0.5 }
+    }
+}
+
+impl Filter {
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Self::Box { radius }
+            | Self::Tent { radius }
+            | Self::Gaussian { radius, .. }
+            | Self::Mitchell { radius, .. }
+            | Self::Lanczos { radius } => radius,
+        }
+    }
+
+    /// A number identifying the kind of filter, and its parameters padded
+    /// with zeros, for recording which filter produced accumulated weights.
+    pub fn parameters(&self) -> (u32, [f64; 3]) {
+        match *self {
+            Self::Box { radius } => (0, [radius, 0.0, 0.0]),
+            Self::Tent { radius } => (1, [radius, 0.0, 0.0]),
+            Self::Gaussian { radius, alpha } => (2, [radius, alpha, 0.0]),
+            Self::Mitchell { radius, b, c } => (3, [radius, b, c]),
+            Self::Lanczos { radius } => (4, [radius, 0.0, 0.0]),
+        }
+    }
+
+    /// Weight of a sample at offset `(x, y)` from the pixel center.
+    pub fn eval(&self, x: f64, y: f64) -> f64 {
+        self.eval_1d(x) * self.eval_1d(y)
+    }
+
+    fn eval_1d(&self, x: f64) -> f64 {
+        match *self {
+            // Half-open so a sample on a pixel border lands in exactly one pixel.
+            Self::Box { radius } => {
+                if -radius <= x && x < radius {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Self::Tent { radius } => (radius - x.abs()).max(0.0),
+            Self::Gaussian { radius, alpha } => {
+                ((-alpha * x * x).exp() - (-alpha * radius * radius).exp()).max(0.0)
+            }
+            Self::Mitchell { radius, b, c } => mitchell(2.0 * x / radius, b, c),
+            Self::Lanczos { radius } => {
+                if x.abs() < radius {
+                    sinc(x) * sinc(x / radius)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+fn mitchell(x: f64, b: f64, c: f64) -> f64 {
+    let x = x.abs();
+    if x < 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+            + (-18.0 + 12.0 * b + 6.0 * c) * x.powi(2)
+            + (6.0 - 2.0 * b))
+            / 6.0
+    } else if x < 2.0 {
+        ((-b - 6.0 * c) * x.powi(3)
+            + (6.0 * b + 30.0 * c) * x.powi(2)
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        0.0
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
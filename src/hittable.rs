@@ -3,14 +3,27 @@ use std::sync::Arc;
 use crate::{
     interval::Interval,
     material::Material,
+    onb::Onb,
     ray::Ray,
     vec3::{Point3, Vec3},
 };
 
+#[derive(Clone)]
 pub struct HitRecord {
     pub p: Point3,
+    /// Geometric normal, facing the side the ray came from.
     pub normal: Vec3,
+    /// Normal materials shade with, perturbed by normal or bump maps; on the
+    /// same side as `normal`.
+    pub shading_normal: Vec3,
+    /// Surface derivatives `∂p/∂u` and `∂p/∂v` along the texture
+    /// coordinates, not normalized.
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
     pub t: f64,
+    /// Surface texture coordinates of `p`.
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
     pub mat: Arc<Material>,
 }
@@ -24,14 +37,29 @@ impl HitRecord {
             -outward_normal
         };
 
+        // Some tangents until the shape sets its own.
+        let frame = Onb::new(&outward_normal);
+        let tangent = frame.to_world(&Vec3::new(1.0, 0.0, 0.0));
+        let bitangent = frame.to_world(&Vec3::new(0.0, 1.0, 0.0));
+
         Self {
             p,
             normal,
+            shading_normal: normal,
+            tangent,
+            bitangent,
             t,
+            u: 0.0,
+            v: 0.0,
             front_face,
             mat,
         }
     }
+
+    /// Shading frame around `shading_normal`, aligned with the tangent.
+    pub fn shading_frame(&self) -> Onb {
+        Onb::with_tangent(&self.shading_normal, &self.tangent)
+    }
 }
 
 pub trait Hittable {
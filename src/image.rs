@@ -0,0 +1,166 @@
+use std::{fs, io, path::Path};
+
+/// A decoded image with channel values scaled to `0.0..=1.0`, row by row from
+/// the top left.
+#[derive(Debug)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    channels: usize,
+    data: Vec<f64>,
+}
+
+impl Image {
+    /// Loads a Netpbm image: grayscale (`P2`, `P5`) or color (`P3`, `P6`).
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let mut header = Header {
+            bytes: &bytes,
+            pos: 0,
+        };
+
+        let magic = header.token()?;
+        let (channels, binary) = match magic {
+            "P2" => (1, false),
+            "P3" => (3, false),
+            "P5" => (1, true),
+            "P6" => (3, true),
+            _ => return Err(invalid("not a grayscale or color Netpbm image")),
+        };
+        let width = header.number()?;
+        let height = header.number()?;
+        let max = header.number()?;
+        if max == 0 || max > u32::from(u16::MAX) {
+            return Err(invalid("unsupported Netpbm maximum value"));
+        }
+
+        let len =
+            usize::try_from(width * height).map_err(|_| invalid("image too large"))? * channels;
+        let scale = f64::from(max).recip();
+        let data = if binary {
+            // A single whitespace byte separates the header from the raster.
+            let raster = bytes.get(header.pos + 1..).unwrap_or_default();
+            let wide = max > 255;
+            let stride = if wide { 2 } else { 1 };
+            if raster.len() < len * stride {
+                return Err(invalid("truncated Netpbm raster"));
+            }
+            (0..len)
+                .map(|n| {
+                    let value = if wide {
+                        u16::from_be_bytes([raster[2 * n], raster[2 * n + 1]])
+                    } else {
+                        u16::from(raster[n])
+                    };
+                    f64::from(value) * scale
+                })
+                .collect()
+        } else {
+            (0..len)
+                .map(|_| header.number().map(|value| f64::from(value) * scale))
+                .collect::<io::Result<_>>()?
+        };
+
+        Ok(Self {
+            width,
+            height,
+            channels,
+            data,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Channel values of pixel `(i, j)`: one for grayscale, three for color.
+    pub fn pixel(&self, i: u32, j: u32) -> &[f64] {
+        let n = usize::try_from(j * self.width + i).expect("pixel out of range") * self.channels;
+        &self.data[n..n + self.channels]
+    }
+
+    /// Channel values of the pixel covering texture coordinates `(u, v)`,
+    /// which span the image from `0.0` to `1.0` with `v` running up from the
+    /// bottom row.
+    pub fn at_uv(&self, u: f64, v: f64) -> &[f64] {
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_sign_loss)]
+        let texel =
+            |t: f64, size: u32| ((t.clamp(0.0, 1.0) * f64::from(size)) as u32).min(size - 1);
+        self.pixel(texel(u, self.width), texel(1.0 - v, self.height))
+    }
+
+    /// Average of the channels at texture coordinates `(u, v)`, as placed by
+    /// `at_uv`, interpolated bilinearly between pixel centers.
+    pub fn gray_uv(&self, u: f64, v: f64) -> f64 {
+        let x = u.clamp(0.0, 1.0) * f64::from(self.width) - 0.5;
+        let y = (1.0 - v).clamp(0.0, 1.0) * f64::from(self.height) - 0.5;
+        let (tx, ty) = (x - x.floor(), y - y.floor());
+        #[allow(clippy::cast_possible_truncation)]
+        #[allow(clippy::cast_sign_loss)]
+        let texel = |t: f64, size: u32| t.clamp(0.0, f64::from(size - 1)) as u32;
+        let gray = |dx: f64, dy: f64| {
+            self.gray(
+                texel(x.floor() + dx, self.width),
+                texel(y.floor() + dy, self.height),
+            )
+        };
+        let top = (1.0 - tx) * gray(0.0, 0.0) + tx * gray(1.0, 0.0);
+        let bottom = (1.0 - tx) * gray(0.0, 1.0) + tx * gray(1.0, 1.0);
+        (1.0 - ty) * top + ty * bottom
+    }
+
+    /// Average of the channels of pixel `(i, j)`.
+    pub fn gray(&self, i: u32, j: u32) -> f64 {
+        let pixel = self.pixel(i, j);
+        #[allow(clippy::cast_precision_loss)]
+        let count = pixel.len() as f64;
+        pixel.iter().sum::<f64>() / count
+    }
+}
+
+/// Reads the whitespace separated, `#` commented text of a Netpbm file.
+struct Header<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Header<'a> {
+    fn token(&mut self) -> io::Result<&'a str> {
+        loop {
+            match self.bytes.get(self.pos) {
+                Some(b'#') => {
+                    while self.bytes.get(self.pos).is_some_and(|&b| b != b'\n') {
+                        self.pos += 1;
+                    }
+                }
+                Some(b) if b.is_ascii_whitespace() => self.pos += 1,
+                Some(_) => break,
+                None => return Err(invalid("unexpected end of Netpbm file")),
+            }
+        }
+        let start = self.pos;
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(|b| !b.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.pos]).map_err(|_| invalid("bad Netpbm header"))
+    }
+
+    fn number(&mut self) -> io::Result<u32> {
+        self.token()?
+            .parse()
+            .map_err(|_| invalid("bad number in Netpbm file"))
+    }
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
@@ -0,0 +1,256 @@
+use std::{fs, io, path::Path};
+
+use crate::{
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+/// One spherical surface (or the aperture stop) of a lens prescription.
+/// Lengths are in world units.
+#[derive(Clone, Copy, Debug)]
+struct LensElement {
+    /// Signed radius of curvature; zero marks the aperture stop.
+    curvature_radius: f64,
+    /// Distance to the next surface towards the film.
+    thickness: f64,
+    /// Index of refraction of the medium behind this surface; zero means air.
+    ior: f64,
+    aperture_radius: f64,
+}
+
+/// A stack of lens elements, traced surface by surface.
+///
+/// Rays are given in camera space: the film sits in the `z = 0` plane and the
+/// elements lie along `+z` in front of it, the last element's thickness being
+/// its distance to the film.
+#[derive(Clone, Debug)]
+pub struct LensSystem {
+    elements: Vec<LensElement>,
+}
+
+impl LensSystem {
+    /// Loads a prescription with one surface per line, front to back:
+    /// curvature radius, thickness, index of refraction and aperture
+    /// diameter, all lengths in millimeters. Blank lines and `#` comments are
+    /// skipped.
+    ///
+    /// Scene units are taken to be meters. See `lenses/` for examples.
+    #[allow(unused)]
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut elements = Vec::new();
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values = line
+                .split_whitespace()
+                .map(str::parse::<f64>)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            let [radius, thickness, ior, aperture] = values[..] else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("expected 4 values per lens surface, found {line:?}"),
+                ));
+            };
+            elements.push(LensElement {
+                curvature_radius: radius * 0.001,
+                thickness: thickness * 0.001,
+                ior,
+                aperture_radius: aperture * 0.001 / 2.0,
+            });
+        }
+
+        if elements.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "lens prescription has no surfaces",
+            ));
+        }
+        Ok(Self { elements })
+    }
+
+    fn front_z(&self) -> f64 {
+        self.elements.iter().map(|element| element.thickness).sum()
+    }
+
+    fn rear(&self) -> &LensElement {
+        self.elements.last().expect("lens has elements")
+    }
+
+    pub fn rear_z(&self) -> f64 {
+        self.rear().thickness
+    }
+
+    pub fn rear_aperture_radius(&self) -> f64 {
+        self.rear().aperture_radius
+    }
+
+    /// Returns the lens with its film moved so that objects `focus_distance`
+    /// in front of the film are sharp, using a thick lens approximation.
+    pub fn focused(&self, focus_distance: f64) -> Option<Self> {
+        // Parallel rays close enough to the axis to pass every element.
+        let x = 0.01
+            * self
+                .elements
+                .iter()
+                .map(|element| element.aperture_radius)
+                .fold(f64::INFINITY, f64::min);
+        let scene_ray = Ray::new(
+            Point3::new(x, 0.0, self.front_z() + 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        let film_ray = self.trace_from_scene(&scene_ray)?;
+        let (pz0, fz0) = cardinal_points(&scene_ray, &film_ray);
+
+        let film_ray = Ray::new(
+            Point3::new(x, 0.0, self.rear_z() - 1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        let scene_ray = self.trace_from_film(&film_ray)?;
+        let (pz1, _) = cardinal_points(&film_ray, &scene_ray);
+
+        let f = fz0 - pz0;
+        let z = -focus_distance;
+        let c = (pz1 - z - pz0) * (pz1 - z - 4.0 * f - pz0);
+        if c <= 0.0 {
+            return None;
+        }
+        let delta = 0.5 * (pz1 - z + pz0 - c.sqrt());
+
+        let mut lens = self.clone();
+        let rear = lens.elements.last_mut().expect("lens has elements");
+        rear.thickness += delta;
+        Some(lens)
+    }
+
+    /// Traces a ray leaving the film towards the front of the lens; `None`
+    /// when an element's rim or the stop blocks it, or it is totally
+    /// internally reflected.
+    pub fn trace_from_film(&self, ray: &Ray) -> Option<Ray> {
+        let mut ray = to_lens_space(ray);
+        let mut z = 0.0;
+        for (i, element) in self.elements.iter().enumerate().rev() {
+            z -= element.thickness;
+            let eta_t = match i {
+                0 => 1.0,
+                _ => medium(self.elements[i - 1].ior),
+            };
+            ray = refract_at(element, z, &ray, medium(element.ior), eta_t)?;
+        }
+        Some(to_lens_space(&ray))
+    }
+
+    fn trace_from_scene(&self, ray: &Ray) -> Option<Ray> {
+        let mut ray = to_lens_space(ray);
+        let mut z = -self.front_z();
+        for (i, element) in self.elements.iter().enumerate() {
+            let eta_i = match i {
+                0 => 1.0,
+                _ => medium(self.elements[i - 1].ior),
+            };
+            ray = refract_at(element, z, &ray, eta_i, medium(element.ior))?;
+            z += element.thickness;
+        }
+        Some(to_lens_space(&ray))
+    }
+}
+
+/// Moves `ray` onto the surface of `element`, whose vertex is at `z`, and
+/// bends it from a medium of index `eta_i` into one of index `eta_t`.
+fn refract_at(element: &LensElement, z: f64, ray: &Ray, eta_i: f64, eta_t: f64) -> Option<Ray> {
+    let (t, normal) = if element.curvature_radius == 0.0 {
+        ((z - ray.origin().z()) / ray.direction().z(), None)
+    } else {
+        let (t, normal) = intersect_spherical(element.curvature_radius, z, ray)?;
+        (t, Some(normal))
+    };
+
+    let hit = ray.at(t);
+    if hit.x() * hit.x() + hit.y() * hit.y() > element.aperture_radius * element.aperture_radius {
+        return None;
+    }
+
+    let direction = match normal {
+        None => *ray.direction(),
+        Some(normal) => refract(&-ray.direction().unit(), &normal, eta_i / eta_t)?,
+    };
+    Some(Ray::new(hit, direction))
+}
+
+fn medium(ior: f64) -> f64 {
+    if ior == 0.0 {
+        1.0
+    } else {
+        ior
+    }
+}
+
+/// Flips `z`, converting between camera space, where the lens looks down
+/// `+z`, and lens space. The conversion is its own inverse.
+fn to_lens_space(ray: &Ray) -> Ray {
+    let o = ray.origin();
+    let d = ray.direction();
+    Ray::new(
+        Point3::new(o.x(), o.y(), -o.z()),
+        Vec3::new(d.x(), d.y(), -d.z()),
+    )
+}
+
+/// Intersects a spherical surface whose vertex is at `z` with `ray`, returning
+/// the distance and the normal facing back along the ray.
+fn intersect_spherical(radius: f64, z: f64, ray: &Ray) -> Option<(f64, Vec3)> {
+    let z_center = z + radius;
+    let o = ray.origin() - Point3::new(0.0, 0.0, z_center);
+    let d = ray.direction();
+
+    let a = d.len_squared();
+    let b = 2.0 * Vec3::dot(d, &o);
+    let c = o.len_squared() - radius * radius;
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    let t0 = (-b - sqrtd) / (2.0 * a);
+    let t1 = (-b + sqrtd) / (2.0 * a);
+
+    let use_closer = (d.z() > 0.0) ^ (radius < 0.0);
+    let t = if use_closer { t0.min(t1) } else { t0.max(t1) };
+    if t < 0.0 {
+        return None;
+    }
+
+    let normal = (o + t * d).unit();
+    let normal = if Vec3::dot(&normal, &-d) < 0.0 {
+        -normal
+    } else {
+        normal
+    };
+    Some((t, normal))
+}
+
+/// Refracts `wi`, pointing away from the surface on the side of `n`, with
+/// relative index `eta`; `None` on total internal reflection.
+fn refract(wi: &Vec3, n: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_theta_i = Vec3::dot(n, wi);
+    let sin2_theta_t = eta * eta * (1.0 - cos_theta_i * cos_theta_i).max(0.0);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some(eta * -wi + (eta * cos_theta_i - cos_theta_t) * n)
+}
+
+/// Principal plane and focal point positions along `z` of the thick lens
+/// that bends the axis-parallel `r_in` into `r_out`.
+fn cardinal_points(r_in: &Ray, r_out: &Ray) -> (f64, f64) {
+    let tf = -r_out.origin().x() / r_out.direction().x();
+    let fz = -r_out.at(tf).z();
+    let tp = (r_in.origin().x() - r_out.origin().x()) / r_out.direction().x();
+    let pz = -r_out.at(tp).z();
+    (pz, fz)
+}
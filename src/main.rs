@@ -1,28 +1,47 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
+mod aperture;
+mod bump;
 mod camera;
 mod color;
+mod exr;
+mod film;
+mod filter;
 mod hittable;
 mod hittable_list;
+mod image;
 mod interval;
+mod lens;
 mod material;
+mod medium;
+mod microfacet;
+mod onb;
+mod png;
+mod principled;
+mod random;
 mod ray;
+mod spectrum;
 mod sphere;
+mod texture;
+mod thin_film;
+mod tile;
+mod tonemap;
 mod vec3;
 
 use camera::{Camera, Settings};
 use color::Color;
 use hittable_list::HittableList;
-use material::Material;
-use rand::Rng;
+use material::{Ior, Material};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use sphere::Sphere;
 use vec3::{Point3, Vec3};
 
-fn main() {
+fn main() -> std::io::Result<()> {
     let mut world = HittableList::default();
+    // A fixed scene seed keeps checkpoints resumable across runs.
+    let mut rng = StdRng::seed_from_u64(0);
 
     for a in -11..11 {
         for b in -11..11 {
-            let mut rng = rand::thread_rng();
             let choose_mat = rng.gen_range(0.0..1.0);
             let center = Point3::new(
                 f64::from(a) + rng.gen_range(0.0..0.9),
@@ -69,7 +88,7 @@ fn main() {
     }
 
     {
-        let material = Arc::new(Material::dielectric(1.5));
+        let material = Arc::new(Material::dispersive(Ior::BK7));
         let center = Point3::new(0.0, 1.0, 0.0);
         let sphere = Sphere::new(center, 1.0, material);
         world.add(Box::new(sphere));
@@ -100,8 +119,10 @@ fn main() {
         vup: Vec3::new(0.0, 1.0, 0.0),
         defocus_angle: 0.6,
         focus_dist: 10.0,
+        snapshot_path: Some(PathBuf::from("snapshot.ppm")),
+        ..Settings::default()
     };
 
-    let camera = Camera::new(settings);
-    camera.render(&world);
+    let camera = Camera::new(settings)?;
+    camera.render(&world)
 }
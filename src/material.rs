@@ -1,4 +1,19 @@
-use crate::{color::Color, hittable::HitRecord, ray::Ray, vec3::Vec3};
+use std::sync::Arc;
+
+use crate::{
+    bump::BumpMap,
+    color::{self, Color},
+    hittable::HitRecord,
+    medium::Medium,
+    microfacet::{self, TrowbridgeReitz},
+    principled::Principled,
+    random,
+    ray::Ray,
+    spectrum::{self, LAMBDA_MAX, LAMBDA_MIN},
+    texture::Texture,
+    thin_film::{Complex, ThinFilm},
+    vec3::{Point3, Vec3},
+};
 
 pub struct ScatterResult {
     pub attenuation: Color,
@@ -6,9 +21,195 @@ pub struct ScatterResult {
 }
 
 pub enum Material {
-    Lambertian { albedo: Color },
-    Metal { albedo: Color, fuzz: f64 },
-    Dielectric { refraction_index: f64 },
+    Lambertian {
+        albedo: Color,
+    },
+    /// Rough diffuse surface made of Lambertian microfacets (Oren-Nayar),
+    /// with `a` and `b` derived from the slope deviation.
+    OrenNayar {
+        albedo: Color,
+        a: f64,
+        b: f64,
+    },
+    /// A thin diffuser, such as a leaf or a lampshade, scattering into both
+    /// hemispheres.
+    Translucent {
+        reflectance: Color,
+        transmittance: Color,
+    },
+    Metal {
+        albedo: Color,
+        fuzz: f64,
+    },
+    Dielectric {
+        refraction_index: Ior,
+        film: Option<ThinFilm>,
+    },
+    /// A metal with GGX microfacet roughness.
+    Conductor {
+        ior: ComplexIor,
+        distribution: TrowbridgeReitz,
+        film: Option<ThinFilm>,
+    },
+    /// Glass with GGX microfacet roughness, such as frosted or etched glass.
+    RoughDielectric {
+        refraction_index: Ior,
+        distribution: TrowbridgeReitz,
+    },
+    /// The Disney principled uber-material.
+    Principled(Principled),
+    /// A clear or tinted dielectric coat, such as varnish or the clearcoat of
+    /// car paint, over another material.
+    Coated {
+        base: Arc<Material>,
+        /// Index of refraction of the coat.
+        ior: f64,
+        distribution: TrowbridgeReitz,
+        /// Fraction of each channel getting through the coat at normal
+        /// incidence, for a coat one unit thick.
+        color: Color,
+        thickness: f64,
+    },
+    /// A dielectric boundary around a scattering medium, for skin, marble
+    /// and wax; light entering it random walks until it leaves again.
+    Subsurface {
+        refraction_index: Ior,
+        medium: Medium,
+    },
+    /// Shades `base` with the normals of a normal or bump map.
+    Bumped {
+        base: Arc<Material>,
+        map: BumpMap,
+    },
+    /// Picks `b` with the probability `factor` gives and `a` otherwise,
+    /// blending the two.
+    Mix {
+        a: Arc<Material>,
+        b: Arc<Material>,
+        factor: Texture,
+    },
+    /// Lets light through where `opacity` falls below one, as for the
+    /// cutouts of leaves and fences.
+    Masked {
+        base: Arc<Material>,
+        opacity: Texture,
+    },
+}
+
+/// Light bouncing more often than this between a coat and its base is
+/// taken as absorbed.
+const MAX_COAT_BOUNCES: u32 = 64;
+
+/// Light scattering more often than this between the microfacets of the
+/// coat interface is taken as absorbed.
+const MAX_INTERFACE_EVENTS: u32 = 16;
+
+/// Complex index of refraction `eta + i k` of a conductor, per RGB channel.
+#[derive(Clone, Copy, Debug)]
+pub struct ComplexIor {
+    pub eta: Color,
+    pub k: Color,
+}
+
+#[allow(unused)]
+impl ComplexIor {
+    pub const GOLD: Self = Self {
+        eta: Color::new(0.143, 0.374, 1.442),
+        k: Color::new(3.983, 2.385, 1.603),
+    };
+    pub const COPPER: Self = Self {
+        eta: Color::new(0.200, 0.924, 1.102),
+        k: Color::new(3.912, 2.452, 2.142),
+    };
+    pub const ALUMINUM: Self = Self {
+        eta: Color::new(1.657, 0.880, 0.521),
+        k: Color::new(9.224, 6.270, 4.837),
+    };
+    pub const SILVER: Self = Self {
+        eta: Color::new(0.155, 0.117, 0.138),
+        k: Color::new(4.828, 3.122, 2.147),
+    };
+
+    /// The index at `lambda` nanometers, interpolated between the channels,
+    /// which are taken at 650, 550 and 450 nm.
+    pub fn at(&self, lambda: f64) -> Complex {
+        let t = ((lambda - 450.0) / 100.0).clamp(0.0, 2.0);
+        let (i, t) = if t < 1.0 { (2, t) } else { (1, t - 1.0) };
+        let lerp = |c: &Color| (1.0 - t) * c.at(i) + t * c.at(i - 1);
+        Complex::new(lerp(&self.eta), lerp(&self.k))
+    }
+
+    /// Reflectance of each channel for light at `cos_theta` to the normal.
+    pub fn fresnel(&self, cos_theta: f64) -> Color {
+        let channel = |i| microfacet::fresnel_conductor(cos_theta, self.eta.at(i), self.k.at(i));
+        Color::new(channel(0), channel(1), channel(2))
+    }
+}
+
+/// Index of refraction of a dielectric as a function of wavelength.
+#[derive(Clone, Copy, Debug)]
+#[allow(unused)]
+pub enum Ior {
+    /// The same index at every wavelength: no dispersion.
+    Constant(f64),
+    /// `n = a + b / λ²`, with `λ` in micrometers.
+    Cauchy { a: f64, b: f64 },
+    /// `n² = 1 + Σ bᵢ λ² / (λ² - cᵢ)`, with `λ` in micrometers.
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+#[allow(unused)]
+impl Ior {
+    /// Schott N-BK7 crown glass.
+    pub const BK7: Self = Self::Sellmeier {
+        b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+        c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+    };
+    /// Fused silica (Malitson 1965).
+    pub const FUSED_SILICA: Self = Self::Sellmeier {
+        b: [0.696_166_3, 0.407_942_6, 0.897_479_4],
+        c: [0.004_679_148_26, 0.013_512_063_1, 97.934_002_5],
+    };
+    /// Diamond (Peter 1923).
+    pub const DIAMOND: Self = Self::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030_625, 0.011_236, 0.0],
+    };
+
+    /// The index at `lambda` nanometers.
+    pub fn at(&self, lambda: f64) -> f64 {
+        let l2 = (lambda * 0.001).powi(2);
+        match *self {
+            Self::Constant(n) => n,
+            Self::Cauchy { a, b } => a + b / l2,
+            Self::Sellmeier { b, c } => {
+                let n2 = 1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>();
+                n2.sqrt()
+            }
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Self::Constant(_))
+    }
+
+    /// The index seen by `ray`, with the wavelength the path continues with
+    /// and the tint that choice puts on it.
+    ///
+    /// A dispersive dielectric bends every wavelength differently, so the
+    /// path continues with a single one. Rays that don't carry one yet pick
+    /// it here, tinted by its color.
+    fn for_ray(&self, ray: &Ray) -> (f64, Option<f64>, Color) {
+        let (wavelength, tint) = match ray.wavelength() {
+            _ if !self.is_dispersive() => (ray.wavelength(), Color::new(1.0, 1.0, 1.0)),
+            Some(lambda) => (Some(lambda), Color::new(1.0, 1.0, 1.0)),
+            None => {
+                let lambda = random::gen_range(LAMBDA_MIN..LAMBDA_MAX);
+                (Some(lambda), spectrum::wavelength_color(lambda))
+            }
+        };
+        (self.at(wavelength.unwrap_or(550.0)), wavelength, tint)
+    }
 }
 
 impl Material {
@@ -16,25 +217,76 @@ impl Material {
     pub fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<ScatterResult> {
         match self {
             Self::Lambertian { albedo } => {
-                let mut scatter_direction = hit_record.normal + Vec3::random_unit_vector();
+                let mut scatter_direction = hit_record.shading_normal + Vec3::random_unit_vector();
 
                 if scatter_direction.near_zero() {
-                    scatter_direction = hit_record.normal;
+                    scatter_direction = hit_record.shading_normal;
                 }
 
                 let result = ScatterResult {
-                    scattered: Ray::new(hit_record.p, scatter_direction),
+                    scattered: Ray::new(hit_record.p, scatter_direction)
+                        .with_wavelength(r_in.wavelength()),
                     attenuation: *albedo,
                 };
                 Some(result)
             }
+            Self::OrenNayar { albedo, a, b } => {
+                let frame = hit_record.shading_frame();
+                let wo = frame.to_local(&-r_in.direction().unit());
+                let wi = cosine_direction();
+
+                let sin_o = (1.0 - wo.z() * wo.z()).max(0.0).sqrt();
+                let sin_i = (1.0 - wi.z() * wi.z()).max(0.0).sqrt();
+                let cos_phi = if sin_o > 1e-4 && sin_i > 1e-4 {
+                    ((wi.x() * wo.x() + wi.y() * wo.y()) / (sin_i * sin_o)).max(0.0)
+                } else {
+                    0.0
+                };
+                let (sin_alpha, tan_beta) = if wi.z().abs() > wo.z().abs() {
+                    (sin_o, sin_i / wi.z().abs())
+                } else {
+                    (sin_i, sin_o / wo.z().abs())
+                };
+
+                let result = ScatterResult {
+                    scattered: Ray::new(hit_record.p, frame.to_world(&wi))
+                        .with_wavelength(r_in.wavelength()),
+                    attenuation: (a + b * cos_phi * sin_alpha * tan_beta) * *albedo,
+                };
+                Some(result)
+            }
+            Self::Translucent {
+                reflectance,
+                transmittance,
+            } => {
+                let r = color::luminance(reflectance);
+                let t = color::luminance(transmittance);
+                if r + t <= 0.0 {
+                    return None;
+                }
+                let frame = hit_record.shading_frame();
+                let wi = cosine_direction();
+                let (wi, attenuation) = if random::random::<f64>() * (r + t) < r {
+                    (wi, (r + t) / r * *reflectance)
+                } else {
+                    (-wi, (r + t) / t * *transmittance)
+                };
+
+                let result = ScatterResult {
+                    scattered: Ray::new(hit_record.p, frame.to_world(&wi))
+                        .with_wavelength(r_in.wavelength()),
+                    attenuation,
+                };
+                Some(result)
+            }
             Self::Metal { albedo, fuzz } => {
-                let reflected = Vec3::reflect(r_in.direction(), &hit_record.normal).unit()
+                let reflected = Vec3::reflect(r_in.direction(), &hit_record.shading_normal).unit()
                     + (Vec3::random_unit_vector() * fuzz);
 
-                let scattered = Ray::new(hit_record.p, reflected);
+                let scattered =
+                    Ray::new(hit_record.p, reflected).with_wavelength(r_in.wavelength());
 
-                if Vec3::dot(scattered.direction(), &hit_record.normal) <= 0.0 {
+                if Vec3::dot(scattered.direction(), &hit_record.shading_normal) <= 0.0 {
                     return None;
                 }
 
@@ -44,24 +296,55 @@ impl Material {
                 };
                 Some(result)
             }
-            Self::Dielectric { refraction_index } => {
-                let attenuation = Color::new(1.0, 1.0, 1.0);
+            Self::Dielectric {
+                refraction_index: ior,
+                film,
+            } => {
+                let (refraction_index, wavelength, tint) = ior.for_ray(r_in);
                 let ri = if hit_record.front_face {
                     refraction_index.recip()
                 } else {
-                    *refraction_index
+                    refraction_index
                 };
 
                 let unit_direction = r_in.direction().unit();
-                let cos_theta = Vec3::dot(&-unit_direction, &hit_record.normal).min(1.0);
+                let cos_theta = Vec3::dot(&-unit_direction, &hit_record.shading_normal).min(1.0);
                 let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
                 let cannot_refract = ri * sin_theta > 1.0;
-                let direction = if cannot_refract || reflectance(cos_theta, ri) > rand::random() {
-                    Vec3::reflect(&unit_direction, &hit_record.normal)
+                let white = Color::new(1.0, 1.0, 1.0);
+                let (reflect, weight) = match film {
+                    _ if cannot_refract => (true, white),
+                    None => (reflectance(cos_theta, ri) > random::random(), white),
+                    Some(film) => {
+                        // Reflect in proportion to the film's mean
+                        // reflectance and weight by its color.
+                        let (eta_i, substrate) = if hit_record.front_face {
+                            (1.0, Some(ior))
+                        } else {
+                            (refraction_index, None)
+                        };
+                        let r = film.reflectance(
+                            cos_theta,
+                            eta_i,
+                            |lambda| Complex::new(substrate.map_or(1.0, |ior| ior.at(lambda)), 0.0),
+                            wavelength,
+                            (hit_record.u, hit_record.v, &hit_record.p),
+                        );
+                        let p = (r.x() + r.y() + r.z()) / 3.0;
+                        if random::random::<f64>() < p {
+                            (true, r / p)
+                        } else {
+                            (false, (white - r) / (1.0 - p))
+                        }
+                    }
+                };
+                let direction = if reflect {
+                    Vec3::reflect(&unit_direction, &hit_record.shading_normal)
                 } else {
-                    Vec3::refract(&unit_direction, &hit_record.normal, ri)
+                    Vec3::refract(&unit_direction, &hit_record.shading_normal, ri)
                 };
-                let scattered = Ray::new(hit_record.p, direction);
+                let attenuation = tint * weight;
+                let scattered = Ray::new(hit_record.p, direction).with_wavelength(wavelength);
 
                 let result = ScatterResult {
                     attenuation,
@@ -69,6 +352,209 @@ impl Material {
                 };
                 Some(result)
             }
+            Self::Conductor {
+                ior,
+                distribution,
+                film,
+            } => {
+                let frame = hit_record.shading_frame();
+                let wo = frame.to_local(&-r_in.direction().unit());
+                if wo.z() <= 0.0 {
+                    return None;
+                }
+
+                let (wi, cos_theta, weight) = distribution.sample_reflection(&wo)?;
+
+                let scattered =
+                    Ray::new(hit_record.p, frame.to_world(&wi)).with_wavelength(r_in.wavelength());
+                let result = ScatterResult {
+                    attenuation: weight
+                        * match film {
+                            None => ior.fresnel(cos_theta),
+                            Some(film) => film.reflectance(
+                                cos_theta,
+                                1.0,
+                                |lambda| ior.at(lambda),
+                                r_in.wavelength(),
+                                (hit_record.u, hit_record.v, &hit_record.p),
+                            ),
+                        },
+                    scattered,
+                };
+                Some(result)
+            }
+            Self::RoughDielectric {
+                refraction_index,
+                distribution,
+            } => {
+                let (refraction_index, wavelength, tint) = refraction_index.for_ray(r_in);
+                // Index of the far side relative to the side the ray comes from.
+                let eta = if hit_record.front_face {
+                    refraction_index
+                } else {
+                    refraction_index.recip()
+                };
+
+                let frame = hit_record.shading_frame();
+                let wo = frame.to_local(&-r_in.direction().unit());
+                if wo.z() <= 0.0 {
+                    return None;
+                }
+                let (wi, weight) = distribution.sample_dielectric(&wo, eta)?;
+
+                let scattered =
+                    Ray::new(hit_record.p, frame.to_world(&wi)).with_wavelength(wavelength);
+                let result = ScatterResult {
+                    attenuation: weight * tint,
+                    scattered,
+                };
+                Some(result)
+            }
+            Self::Principled(principled) => {
+                let frame = hit_record.shading_frame();
+                let wo = frame.to_local(&-r_in.direction().unit());
+                if wo.z() <= 0.0 {
+                    return None;
+                }
+                let (wi, attenuation) = principled.sample(&wo, hit_record.front_face)?;
+
+                let scattered =
+                    Ray::new(hit_record.p, frame.to_world(&wi)).with_wavelength(r_in.wavelength());
+                let result = ScatterResult {
+                    attenuation,
+                    scattered,
+                };
+                Some(result)
+            }
+            Self::Coated {
+                base,
+                ior,
+                distribution,
+                color,
+                thickness,
+            } => {
+                let frame = hit_record.shading_frame();
+                let wo = frame.to_local(&-r_in.direction().unit());
+                if wo.z() <= 0.0 {
+                    return None;
+                }
+                // Transmittance of the coat along `w`, whose path through it
+                // lengthens away from the normal.
+                let absorb = |w: &Vec3| {
+                    let depth = thickness / w.z().abs();
+                    Color::new(
+                        color.x().powf(depth),
+                        color.y().powf(depth),
+                        color.z().powf(depth),
+                    )
+                };
+                // Looks at the coat from below, where it faces `-z`.
+                let flip = |w: Vec3| Vec3::new(w.x(), w.y(), -w.z());
+                let leave = |w: &Vec3, attenuation, wavelength| {
+                    let scattered =
+                        Ray::new(hit_record.p, frame.to_world(w)).with_wavelength(wavelength);
+                    Some(ScatterResult {
+                        attenuation,
+                        scattered,
+                    })
+                };
+
+                let (mut w, weight) = sample_coat(distribution, &wo, *ior)?;
+                let mut attenuation = Color::new(weight, weight, weight);
+                if w.z() > 0.0 {
+                    return leave(&w, attenuation, r_in.wavelength());
+                }
+
+                // Follow the light bouncing between the coat and the base
+                // until it leaves through either, so none is lost in between
+                // beyond what the microfacets of the coat mask.
+                let base_hit = HitRecord {
+                    front_face: true,
+                    mat: Arc::clone(base),
+                    ..hit_record.clone()
+                };
+                let mut wavelength = r_in.wavelength();
+                for _ in 0..MAX_COAT_BOUNCES {
+                    attenuation = attenuation * absorb(&w);
+                    let ray =
+                        Ray::new(hit_record.p, frame.to_world(&w)).with_wavelength(wavelength);
+                    let ScatterResult {
+                        attenuation: base_attenuation,
+                        scattered,
+                    } = base.scatter(&ray, &base_hit)?;
+                    attenuation = attenuation * base_attenuation;
+                    wavelength = scattered.wavelength();
+
+                    let up = frame.to_local(&scattered.direction().unit());
+                    if up.z() <= 0.0 {
+                        // Transmitted by the base, out of the layer.
+                        return Some(ScatterResult {
+                            attenuation,
+                            scattered,
+                        });
+                    }
+                    attenuation = attenuation * absorb(&up);
+
+                    let (down, weight) = sample_coat(distribution, &flip(-up), ior.recip())?;
+                    attenuation *= weight;
+                    w = flip(down);
+                    if w.z() > 0.0 {
+                        return leave(&w, attenuation, wavelength);
+                    }
+                }
+                None
+            }
+            Self::Subsurface {
+                refraction_index,
+                medium,
+            } => {
+                let boundary = Material::dispersive(*refraction_index);
+                let ScatterResult {
+                    attenuation,
+                    scattered,
+                } = boundary.scatter(r_in, hit_record)?;
+                // The normal faces the side the ray came from; pointing the
+                // other way means inside when the ray came from outside, and
+                // the reverse.
+                let crossed = Vec3::dot(scattered.direction(), &hit_record.normal) < 0.0;
+                let inside = crossed == hit_record.front_face;
+                let result = ScatterResult {
+                    attenuation,
+                    scattered: scattered.with_medium(inside.then_some(*medium)),
+                };
+                Some(result)
+            }
+            Self::Mix { a, b, factor } => {
+                let factor = factor.factor(hit_record.u, hit_record.v, &hit_record.p);
+                let mat = if random::random::<f64>() < factor {
+                    b
+                } else {
+                    a
+                };
+                mat.scatter(r_in, hit_record)
+            }
+            Self::Masked { base, .. } => base.scatter(r_in, hit_record),
+            Self::Bumped { base, map } => {
+                let mut hit_record = hit_record.clone();
+                map.apply(&mut hit_record);
+                base.scatter(r_in, &hit_record)
+            }
+        }
+    }
+
+    /// How much of the light reaching the point with texture coordinates
+    /// `(u, v)` the surface stops, from `0.0` for none to `1.0` for all.
+    pub fn opacity(&self, u: f64, v: f64, p: &Point3) -> f64 {
+        match self {
+            Self::Mix { a, b, factor } => {
+                let factor = factor.factor(u, v, p);
+                (1.0 - factor) * a.opacity(u, v, p) + factor * b.opacity(u, v, p)
+            }
+            Self::Bumped { base, .. } | Self::Coated { base, .. } => base.opacity(u, v, p),
+            Self::Masked { base, opacity } => {
+                opacity.factor(u, v, p).clamp(0.0, 1.0) * base.opacity(u, v, p)
+            }
+            _ => 1.0,
         }
     }
 
@@ -76,6 +562,29 @@ impl Material {
         Material::Lambertian { albedo }
     }
 
+    /// An Oren-Nayar surface whose microfacet slopes deviate by `sigma`
+    /// degrees; `0.0` is Lambertian.
+    #[allow(unused)]
+    pub fn oren_nayar(albedo: Color, sigma: f64) -> Self {
+        let sigma2 = sigma.to_radians().powi(2);
+        Material::OrenNayar {
+            albedo,
+            a: 1.0 - sigma2 / (2.0 * (sigma2 + 0.33)),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+        }
+    }
+
+    /// A two-sided diffuser reflecting `reflectance` and letting
+    /// `transmittance` through to the other side; the two should sum to at
+    /// most one.
+    #[allow(unused)]
+    pub fn translucent(reflectance: Color, transmittance: Color) -> Self {
+        Material::Translucent {
+            reflectance,
+            transmittance,
+        }
+    }
+
     pub fn metal(albedo: Color, fuzz: f64) -> Self {
         if fuzz < 1.0 {
             Material::Metal { albedo, fuzz }
@@ -85,7 +594,189 @@ impl Material {
     }
 
     pub fn dielectric(refraction_index: f64) -> Self {
-        Material::Dielectric { refraction_index }
+        Material::Dielectric {
+            refraction_index: Ior::Constant(refraction_index),
+            film: None,
+        }
+    }
+
+    /// A GGX conductor. `roughness` runs from a mirror at `0.0` to fully
+    /// rough at `1.0`; `anisotropy` from `0.0` stretches the highlight.
+    #[allow(unused)]
+    pub fn conductor(ior: ComplexIor, roughness: f64, anisotropy: f64) -> Self {
+        Material::Conductor {
+            ior,
+            distribution: TrowbridgeReitz::new(roughness, anisotropy),
+            film: None,
+        }
+    }
+
+    /// A dielectric with GGX `roughness`, from polished at `0.0` to fully
+    /// frosted at `1.0`.
+    #[allow(unused)]
+    pub fn rough_dielectric(refraction_index: Ior, roughness: f64) -> Self {
+        Material::RoughDielectric {
+            refraction_index,
+            distribution: TrowbridgeReitz::new(roughness, 0.0),
+        }
+    }
+
+    /// A Disney principled material, usually built from
+    /// `Principled::default()` with a few parameters changed.
+    #[allow(unused)]
+    pub fn principled(parameters: Principled) -> Self {
+        Material::Principled(parameters)
+    }
+
+    /// Coats `base` with a dielectric of index `ior` and GGX `roughness`,
+    /// tinted by `color` per unit of `thickness`.
+    #[allow(unused)]
+    pub fn coated(
+        base: Arc<Material>,
+        ior: f64,
+        roughness: f64,
+        color: Color,
+        thickness: f64,
+    ) -> Self {
+        Material::Coated {
+            base,
+            ior,
+            distribution: TrowbridgeReitz::new(roughness, 0.0),
+            color,
+            thickness,
+        }
+    }
+
+    /// A subsurface scattering material behind a smooth boundary of index
+    /// `refraction_index`. Inside, light travels `mean_free_path` between
+    /// scattering events on average and survives each with probability
+    /// `albedo`. Meant for closed objects.
+    #[allow(unused)]
+    pub fn subsurface(refraction_index: f64, albedo: Color, mean_free_path: Color) -> Self {
+        Material::Subsurface {
+            refraction_index: Ior::Constant(refraction_index),
+            medium: Medium::new(albedo, mean_free_path),
+        }
+    }
+
+    /// Blends `a` into `b` by `factor`, from all `a` at `0.0` to all `b` at
+    /// `1.0`.
+    #[allow(unused)]
+    pub fn mix(a: Arc<Material>, b: Arc<Material>, factor: Texture) -> Self {
+        Material::Mix { a, b, factor }
+    }
+
+    /// Shades `base` with the normals `map` gives.
+    #[allow(unused)]
+    pub fn bumped(base: Arc<Material>, map: BumpMap) -> Self {
+        Material::Bumped { base, map }
+    }
+
+    /// Cuts `base` out where `opacity` is below one.
+    #[allow(unused)]
+    pub fn masked(base: Arc<Material>, opacity: Texture) -> Self {
+        Material::Masked { base, opacity }
+    }
+
+    /// A dielectric whose index varies with wavelength, splitting white
+    /// light into its colors.
+    pub fn dispersive(refraction_index: Ior) -> Self {
+        Material::Dielectric {
+            refraction_index,
+            film: None,
+        }
+    }
+
+    /// Puts a thin film on a `Dielectric` or `Conductor`, for soap bubble
+    /// and oil slick iridescence. Other materials are returned unchanged.
+    #[allow(unused)]
+    pub fn with_thin_film(self, thin_film: ThinFilm) -> Self {
+        match self {
+            Self::Dielectric {
+                refraction_index, ..
+            } => Self::Dielectric {
+                refraction_index,
+                film: Some(thin_film),
+            },
+            Self::Conductor {
+                ior, distribution, ..
+            } => Self::Conductor {
+                ior,
+                distribution,
+                film: Some(thin_film),
+            },
+            other => other,
+        }
+    }
+
+    /// Whether the material treats wavelengths so differently that a
+    /// spectral path can only go on with one of them: a dispersive index
+    /// refracts each its own way, and a thin film colors each by its own
+    /// interference.
+    pub fn is_dispersive(&self) -> bool {
+        match self {
+            Self::Dielectric {
+                refraction_index,
+                film,
+            } => refraction_index.is_dispersive() || film.is_some(),
+            Self::Conductor { film, .. } => film.is_some(),
+            Self::RoughDielectric {
+                refraction_index, ..
+            }
+            | Self::Subsurface {
+                refraction_index, ..
+            } => refraction_index.is_dispersive(),
+            Self::Coated { base, .. } | Self::Masked { base, .. } | Self::Bumped { base, .. } => {
+                base.is_dispersive()
+            }
+            Self::Mix { a, b, .. } => a.is_dispersive() || b.is_dispersive(),
+            _ => false,
+        }
+    }
+}
+
+/// Samples the direction light leaves the coat interface in, and the
+/// weight of the sample, like `TrowbridgeReitz::sample_dielectric`. Light
+/// that a microfacet sends to the wrong side of the surface isn't dropped
+/// but meets the microsurface again, from the side it is on by then, until
+/// it leaves on the side it was sent to.
+fn sample_coat(distribution: &TrowbridgeReitz, wo: &Vec3, eta: f64) -> Option<(Vec3, f64)> {
+    let flip = |w: Vec3| Vec3::new(w.x(), w.y(), -w.z());
+    let (mut wo, mut eta) = (*wo, eta);
+    // Whether the light is beyond the interface, where `wo` is seen flipped.
+    let mut beyond = false;
+    let mut weight = 1.0;
+    for _ in 0..MAX_INTERFACE_EVENTS {
+        let (wi, sample_weight, reflected) = distribution.sample_dielectric_event(&wo, eta)?;
+        weight *= sample_weight;
+        let valid = if reflected {
+            wi.z() > 0.0
+        } else {
+            wi.z() < 0.0
+        };
+        if valid {
+            return Some((if beyond { flip(wi) } else { wi }, weight));
+        }
+        if reflected {
+            // Heading down into the microsurface it was reflected off.
+            wo = -wi;
+        } else {
+            // Transmitted but heading back up, into the interface from below.
+            wo = flip(-wi);
+            eta = eta.recip();
+            beyond = !beyond;
+        }
+    }
+    None
+}
+
+/// A cosine-distributed direction in the local shading frame.
+fn cosine_direction() -> Vec3 {
+    let w = Vec3::new(0.0, 0.0, 1.0) + Vec3::random_unit_vector();
+    if w.near_zero() {
+        Vec3::new(0.0, 0.0, 1.0)
+    } else {
+        w.unit()
     }
 }
 
@@ -95,3 +786,169 @@ fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
     let r0 = r0 * r0;
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: u32 = 50_000;
+
+    /// Directional albedo of `mat` seen at `cos_theta` to the normal, from
+    /// outside or, without `front_face`, from inside: the mean weight of
+    /// the light it scatters, which is what it returns of a uniform white
+    /// environment. Materials that neither absorb nor lose paths return one.
+    fn furnace(mat: Material, cos_theta: f64, front_face: bool) -> f64 {
+        random::reseed(1);
+        let mat = Arc::new(mat);
+        let side = if front_face { 1.0 } else { -1.0 };
+        let wo = Vec3::new((1.0 - cos_theta * cos_theta).sqrt(), 0.0, cos_theta);
+        let ray = Ray::new(wo, -wo);
+        let hit = HitRecord::new(
+            1.0,
+            Point3::default(),
+            &ray,
+            side * Vec3::new(0.0, 0.0, 1.0),
+            Arc::clone(&mat),
+        );
+        let sum = (0..SAMPLES).fold(Color::default(), |sum, _| {
+            sum + mat
+                .scatter(&ray, &hit)
+                .map_or(Color::default(), |result| result.attenuation)
+        });
+        (sum.x() + sum.y() + sum.z()) / (3.0 * f64::from(SAMPLES))
+    }
+
+    const WHITE: Color = Color::new(1.0, 1.0, 1.0);
+    /// A conductor reflecting everything at every angle.
+    const MIRROR: ComplexIor = ComplexIor {
+        eta: Color::new(0.0, 0.0, 0.0),
+        k: Color::new(1e3, 1e3, 1e3),
+    };
+
+    /// Viewing angles to test, from head-on to grazing.
+    const COSINES: [f64; 3] = [1.0, 0.5, 0.1];
+
+    /// Allowance for the Monte Carlo noise of `furnace`.
+    const TOLERANCE: f64 = 0.01;
+
+    /// Single scattering microfacet models lose the light that would bounce
+    /// between microfacets, but never create any, and lose little while
+    /// they are fairly smooth.
+    fn assert_microfacet(albedo: impl Fn(f64, f64) -> f64) {
+        for cos_theta in COSINES {
+            let smooth = albedo(0.0, cos_theta);
+            assert!(
+                (smooth - 1.0).abs() < 1e-9,
+                "smooth at {cos_theta}: {smooth}"
+            );
+            let slightly_rough = albedo(0.2, cos_theta);
+            assert!(
+                slightly_rough > 0.85,
+                "roughness 0.2 at {cos_theta}: {slightly_rough}"
+            );
+            for roughness in [0.2, 0.5, 1.0] {
+                let a = albedo(roughness, cos_theta);
+                assert!(
+                    a < 1.0 + TOLERANCE,
+                    "roughness {roughness} at {cos_theta}: {a}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn conductor_furnace() {
+        assert_microfacet(|roughness, cos_theta| {
+            furnace(Material::conductor(MIRROR, roughness, 0.0), cos_theta, true)
+        });
+        assert_microfacet(|roughness, cos_theta| {
+            furnace(Material::conductor(MIRROR, roughness, 0.8), cos_theta, true)
+        });
+    }
+
+    #[test]
+    fn rough_dielectric_furnace() {
+        for front_face in [true, false] {
+            assert_microfacet(|roughness, cos_theta| {
+                let mat = Material::rough_dielectric(Ior::Constant(1.5), roughness);
+                furnace(mat, cos_theta, front_face)
+            });
+        }
+    }
+
+    #[test]
+    fn principled_furnace() {
+        let white = Principled {
+            base_color: WHITE,
+            ..Principled::default()
+        };
+        assert_microfacet(|roughness, cos_theta| {
+            let metal = Principled {
+                metallic: 1.0,
+                roughness,
+                ..white
+            };
+            furnace(Material::principled(metal), cos_theta, true)
+        });
+        for front_face in [true, false] {
+            assert_microfacet(|roughness, cos_theta| {
+                let glass = Principled {
+                    transmission: 1.0,
+                    roughness,
+                    ..white
+                };
+                furnace(Material::principled(glass), cos_theta, front_face)
+            });
+        }
+        // Burley's diffuse is only roughly energy conserving: it darkens
+        // smooth surfaces and brightens rough ones towards grazing angles.
+        let diffuse = Principled {
+            specular: 0.0,
+            ..white
+        };
+        let albedo = furnace(Material::principled(diffuse), 1.0, true);
+        assert!((albedo - 1.0).abs() < 0.05, "diffuse: {albedo}");
+    }
+
+    #[test]
+    fn oren_nayar_furnace() {
+        for cos_theta in COSINES {
+            let smooth = furnace(Material::oren_nayar(WHITE, 0.0), cos_theta, true);
+            assert!(
+                (smooth - 1.0).abs() < 1e-9,
+                "smooth at {cos_theta}: {smooth}"
+            );
+            for sigma in [20.0, 60.0] {
+                let rough = furnace(Material::oren_nayar(WHITE, sigma), cos_theta, true);
+                assert!(rough < 1.0, "sigma {sigma} at {cos_theta}: {rough}");
+            }
+        }
+    }
+
+    #[test]
+    fn translucent_furnace() {
+        let mat = || Material::translucent(0.3 * WHITE, 0.7 * WHITE);
+        for cos_theta in COSINES {
+            for front_face in [true, false] {
+                let albedo = furnace(mat(), cos_theta, front_face);
+                assert!((albedo - 1.0).abs() < 1e-9, "at {cos_theta}: {albedo}");
+            }
+        }
+    }
+
+    #[test]
+    fn coated_furnace() {
+        // A clear coat over a white base only loses what its own microfacets
+        // mask, however often the light bounces in between.
+        let base = Arc::new(Material::lambertian(WHITE));
+        let albedo = |roughness, cos_theta| {
+            let mat = Material::coated(Arc::clone(&base), 1.5, roughness, WHITE, 1.0);
+            furnace(mat, cos_theta, true)
+        };
+        assert_microfacet(albedo);
+        for cos_theta in COSINES {
+            let rough = albedo(1.0, cos_theta);
+            assert!(rough < 1.0 - TOLERANCE, "rough at {cos_theta}: {rough}");
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use crate::{color::Color, random, ray::Ray, vec3::Vec3};
+
+/// Scattering events a random walk through a medium may take before the
+/// light is taken as absorbed. Dense media need thousands.
+pub const MAX_EVENTS: u32 = 1 << 16;
+
+/// A homogeneous participating medium filling the inside of an object,
+/// scattering light isotropically.
+#[derive(Clone, Copy, Debug)]
+pub struct Medium {
+    /// Extinction coefficient per channel, the inverse of the mean free path.
+    sigma_t: Color,
+    /// Scattering coefficient per channel.
+    sigma_s: Color,
+}
+
+impl Medium {
+    /// A medium whose light travels `mean_free_path` between interactions
+    /// on average and survives each with probability `albedo`.
+    pub fn new(albedo: Color, mean_free_path: Color) -> Self {
+        let sigma_t = Color::new(
+            mean_free_path.x().recip(),
+            mean_free_path.y().recip(),
+            mean_free_path.z().recip(),
+        );
+        Self {
+            sigma_t,
+            sigma_s: albedo * sigma_t,
+        }
+    }
+
+    /// Follows `ray` through the medium up to the surface it reaches at
+    /// `t_max`. Returns the continuation ray when the light scatters on the
+    /// way, and the weight of either outcome.
+    ///
+    /// The distance is sampled for a channel picked at random, and weighted
+    /// by the average pdf of all channels (spectral MIS), so colored media
+    /// stay free of fireflies.
+    pub fn interact(&self, ray: &Ray, t_max: f64) -> (Option<Ray>, Color) {
+        let speed = ray.direction().len();
+        let channel = random::gen_range(0..3);
+        let u: f64 = random::random();
+        let distance = -(1.0 - u).ln() / self.sigma_t.at(channel);
+
+        let max_distance = t_max * speed;
+        let scatters = distance < max_distance;
+        let distance = distance.min(max_distance);
+        let transmittance = Color::new(
+            (-self.sigma_t.x() * distance).exp(),
+            (-self.sigma_t.y() * distance).exp(),
+            (-self.sigma_t.z() * distance).exp(),
+        );
+        let average = |c: Color| (c.x() + c.y() + c.z()) / 3.0;
+
+        if scatters {
+            let pdf = average(self.sigma_t * transmittance);
+            let scattered = Ray::new(ray.at(distance / speed), Vec3::random_unit_vector())
+                .with_wavelength(ray.wavelength())
+                .with_medium(Some(*self));
+            (Some(scattered), self.sigma_s * transmittance / pdf)
+        } else {
+            let pdf = average(transmittance);
+            if pdf <= 0.0 {
+                return (None, Color::default());
+            }
+            (None, transmittance / pdf)
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::f64::consts::PI;
+
+use crate::{color::Color, random, vec3::Vec3};
+
+/// The Trowbridge-Reitz (GGX) distribution of microfacet normals, in the
+/// local shading frame where the macro surface normal is `+z`.
+#[derive(Clone, Copy, Debug)]
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    /// Maps a perceptual `roughness` in `0..=1` and an `anisotropy` in
+    /// `0..1`, stretching the highlight along the local `x` axis, to the
+    /// distribution's widths.
+    pub fn new(roughness: f64, anisotropy: f64) -> Self {
+        let alpha = roughness.clamp(0.0, 1.0).powi(2);
+        let aspect = (1.0 - 0.9 * anisotropy.clamp(0.0, 1.0)).sqrt();
+        Self {
+            alpha_x: (alpha / aspect).max(1e-4),
+            alpha_y: (alpha * aspect).max(1e-4),
+        }
+    }
+
+    /// Below this width the surface is treated as a perfect mirror.
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let z2 = w.z() * w.z();
+        if z2 == 0.0 {
+            return f64::INFINITY;
+        }
+        let a2_tan2 = (self.alpha_x * w.x()).powi(2) + (self.alpha_y * w.y()).powi(2);
+        ((1.0 + a2_tan2 / z2).sqrt() - 1.0) / 2.0
+    }
+
+    /// Smith masking of the microsurface seen from `w`.
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Height-correlated Smith masking-shadowing for the pair `wo`, `wi`.
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Samples the mirror reflection of `wo` off a visible microfacet,
+    /// returning the incident direction, its cosine to the microfacet for
+    /// the Fresnel term, and the weight of the sample; `None` when it points
+    /// into the surface.
+    pub fn sample_reflection(&self, wo: &Vec3) -> Option<(Vec3, f64, f64)> {
+        if self.is_smooth() {
+            return Some((Vec3::new(-wo.x(), -wo.y(), wo.z()), wo.z(), 1.0));
+        }
+        let wm = self.sample_visible_normal(wo);
+        let wi = reflect(wo, &wm);
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        // Sampling visible normals leaves only the masking of the outgoing
+        // direction in the weight.
+        Some((wi, Vec3::dot(wo, &wm), self.g(wo, &wi) / self.g1(wo)))
+    }
+
+    /// Samples reflection off or transmission through a dielectric interface
+    /// whose far side has relative index `eta`, returning the incident
+    /// direction and the weight of the sample.
+    pub fn sample_dielectric(&self, wo: &Vec3, eta: f64) -> Option<(Vec3, f64)> {
+        let (wi, weight, reflected) = self.sample_dielectric_event(wo, eta)?;
+        let valid = if reflected {
+            wi.z() > 0.0
+        } else {
+            wi.z() < 0.0
+        };
+        valid.then_some((wi, weight))
+    }
+
+    /// Like `sample_dielectric`, but also returns samples that end up on the
+    /// wrong side of the macro surface, and whether the light was reflected.
+    pub fn sample_dielectric_event(&self, wo: &Vec3, eta: f64) -> Option<(Vec3, f64, bool)> {
+        let wm = if self.is_smooth() {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            self.sample_visible_normal(wo)
+        };
+
+        // Choosing reflection or transmission by the Fresnel term cancels it
+        // from the weight (Walter et al. 2007).
+        let cos_o = Vec3::dot(wo, &wm);
+        let reflected = random::random::<f64>() < fresnel_dielectric(cos_o, eta);
+        let wi = if reflected {
+            reflect(wo, &wm)
+        } else {
+            refract(wo, &wm, eta)?
+        };
+        if self.is_smooth() {
+            return Some((wi, 1.0, reflected));
+        }
+        Some((wi, self.g(wo, &wi) / self.g1(wo), reflected))
+    }
+
+    /// Samples a microfacet normal visible from `wo`, in proportion to its
+    /// projected area (Heitz 2018).
+    fn sample_visible_normal(&self, wo: &Vec3) -> Vec3 {
+        let flip = if wo.z() < 0.0 { -1.0 } else { 1.0 };
+        let vh = Vec3::new(
+            self.alpha_x * wo.x() * flip,
+            self.alpha_y * wo.y() * flip,
+            wo.z() * flip,
+        )
+        .unit();
+
+        let len2 = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if len2 > 0.0 {
+            Vec3::new(-vh.y(), vh.x(), 0.0) / len2.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = Vec3::cross(&vh, &t1);
+
+        let r = random::random::<f64>().sqrt();
+        let phi = 2.0 * PI * random::random::<f64>();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        Vec3::new(
+            self.alpha_x * nh.x(),
+            self.alpha_y * nh.y(),
+            nh.z().max(1e-6),
+        )
+        .unit()
+    }
+}
+
+/// Schlick's approximation of the Fresnel reflectance, from the reflectance
+/// `f0` at normal incidence.
+pub fn fresnel_schlick(cos_theta: f64, f0: Color) -> Color {
+    f0 + (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5) * (Color::new(1.0, 1.0, 1.0) - f0)
+}
+
+/// Mirrors `wo` about the microfacet normal `wm`.
+pub fn reflect(wo: &Vec3, wm: &Vec3) -> Vec3 {
+    2.0 * Vec3::dot(wo, wm) * wm - *wo
+}
+
+/// Refracts `wo` through the microfacet normal `wm` into a medium whose
+/// index relative to that of `wo` is `eta`; `None` on total internal
+/// reflection.
+pub fn refract(wo: &Vec3, wm: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_theta_i = Vec3::dot(wo, wm);
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i).max(0.0) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some(-*wo / eta + (cos_theta_i / eta - cos_theta_t) * wm)
+}
+
+/// Unpolarized Fresnel reflectance of a dielectric interface with relative
+/// index `eta`, for light arriving at `cos_theta_i` to the normal.
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+/// Fresnel reflectance of a conductor with complex index `eta + i k`, for
+/// light arriving at `cos_theta_i` to the normal.
+pub fn fresnel_conductor(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_theta_i.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * a * cos2.sqrt();
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rs + rp)
+}
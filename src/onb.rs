@@ -0,0 +1,51 @@
+use crate::vec3::Vec3;
+
+/// An orthonormal basis around a surface normal `w`, for working in the
+/// local shading frame where the normal is `+z`.
+#[derive(Clone, Debug)]
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    /// Builds a basis around the unit vector `n` (Duff et al. 2017).
+    pub fn new(n: &Vec3) -> Self {
+        let sign = 1.0_f64.copysign(n.z());
+        let a = -1.0 / (sign + n.z());
+        let b = n.x() * n.y() * a;
+        let u = Vec3::new(1.0 + sign * n.x() * n.x() * a, sign * b, -sign * n.x());
+        let v = Vec3::new(b, sign + n.y() * n.y() * a, -n.y());
+        Self { u, v, w: *n }
+    }
+
+    /// Builds a basis around the unit vector `n` whose first axis follows
+    /// `tangent` as closely as it can.
+    pub fn with_tangent(n: &Vec3, tangent: &Vec3) -> Self {
+        let u = *tangent - Vec3::dot(tangent, n) * n;
+        if u.near_zero() {
+            return Self::new(n);
+        }
+        let u = u.unit();
+        Self {
+            u,
+            v: Vec3::cross(n, &u),
+            w: *n,
+        }
+    }
+
+    /// Takes local coordinates to world space.
+    pub fn to_world(&self, local: &Vec3) -> Vec3 {
+        local.x() * self.u + local.y() * self.v + local.z() * self.w
+    }
+
+    /// Takes a world space vector to local coordinates.
+    pub fn to_local(&self, world: &Vec3) -> Vec3 {
+        Vec3::new(
+            Vec3::dot(world, &self.u),
+            Vec3::dot(world, &self.v),
+            Vec3::dot(world, &self.w),
+        )
+    }
+}
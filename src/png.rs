@@ -0,0 +1,187 @@
+use std::io::{self, Write};
+
+use crate::color::ColorSpace;
+
+/// Writes 8-bit RGB `pixels`, row by row, as an uncompressed PNG tagged with
+/// the sRGB transfer function and the primaries of `space`.
+pub fn write(
+    out: &mut impl Write,
+    width: u32,
+    height: u32,
+    pixels: &[[u8; 3]],
+    space: ColorSpace,
+) -> io::Result<()> {
+    out.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, truecolor, deflate, adaptive filtering, no interlace.
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    chunk(out, b"IHDR", &header)?;
+
+    // Decoders prefer cICP, then sRGB, then cHRM with gAMA, so each space is
+    // tagged as precisely as the format allows.
+    match space {
+        ColorSpace::Srgb => {
+            // BT.709 primaries, sRGB transfer, RGB, full range.
+            chunk(out, b"cICP", &[1, 13, 0, 1])?;
+            // Perceptual rendering intent.
+            chunk(out, b"sRGB", &[0])?;
+        }
+        // SMPTE EG 432 (P3 with D65 white), sRGB transfer, RGB, full range.
+        ColorSpace::DisplayP3 => chunk(out, b"cICP", &[12, 13, 0, 1])?,
+        ColorSpace::AcesCg => {}
+    }
+    let mut chromaticities = Vec::with_capacity(32);
+    let [red, green, blue, white] = space.chromaticities();
+    for (x, y) in [white, red, green, blue] {
+        for v in [x, y] {
+            #[allow(clippy::cast_possible_truncation)]
+            #[allow(clippy::cast_sign_loss)]
+            chromaticities.extend_from_slice(&((v * 100_000.0).round() as u32).to_be_bytes());
+        }
+    }
+    chunk(out, b"cHRM", &chromaticities)?;
+    chunk(out, b"gAMA", &45_455_u32.to_be_bytes())?;
+
+    let width = usize::try_from(width).expect("image too wide");
+    let mut raw = Vec::with_capacity(pixels.len() * 3 + pixels.len() / width.max(1));
+    for row in pixels.chunks(width.max(1)) {
+        // Filter type: none.
+        raw.push(0);
+        raw.extend(row.iter().flatten());
+    }
+    chunk(out, b"IDAT", &zlib_stored(&raw))?;
+    chunk(out, b"IEND", &[])
+}
+
+fn chunk(out: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(data.len()).map_err(|_| io::Error::other("PNG chunk too large"))?;
+    out.write_all(&len.to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let crc = crc32(kind.iter().chain(data));
+    out.write_all(&crc.to_be_bytes())
+}
+
+/// Wraps `data` in a zlib stream of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 0xffff;
+
+    let mut stream = Vec::with_capacity(data.len() + data.len() / MAX_BLOCK * 5 + 11);
+    stream.extend_from_slice(&[0x78, 0x01]);
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        stream.push(u8::from(blocks.peek().is_none()));
+        let len = u16::try_from(block.len()).expect("block fits in 16 bits");
+        stream.extend_from_slice(&len.to_le_bytes());
+        stream.extend_from_slice(&(!len).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    !bytes.fold(!0, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65_521;
+    let (a, b) = data.iter().fold((1, 0), |(a, b), &byte| {
+        let a = (a + u32::from(byte)) % MOD;
+        (a, (b + a) % MOD)
+    });
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Splits a PNG file into the kind, data and stored CRC of its chunks.
+    fn chunks(png: &[u8]) -> Vec<(&[u8], &[u8], u32)> {
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (kind, data) = (&rest[4..8], &rest[8..8 + len]);
+            let crc = u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap());
+            chunks.push((kind, data, crc));
+            rest = &rest[12 + len..];
+        }
+        chunks
+    }
+
+    /// Concatenates the uncompressed deflate blocks of a zlib stream,
+    /// checking their framing and the trailing checksum.
+    fn inflate_stored(stream: &[u8]) -> Vec<u8> {
+        assert_eq!(&stream[..2], [0x78, 0x01]);
+        let mut data = Vec::new();
+        let mut rest = &stream[2..];
+        loop {
+            let last = rest[0] == 1;
+            let len = u16::from_le_bytes([rest[1], rest[2]]);
+            assert_eq!(u16::from_le_bytes([rest[3], rest[4]]), !len);
+            let len = usize::from(len);
+            data.extend_from_slice(&rest[5..5 + len]);
+            rest = &rest[5 + len..];
+            if last {
+                break;
+            }
+        }
+        assert_eq!(rest, adler32(&data).to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn crc_matches_reference() {
+        assert_eq!(crc32(b"123456789".iter()), 0xcbf4_3926);
+        assert_eq!(crc32(b"IEND".iter()), 0xae42_6082);
+    }
+
+    #[test]
+    fn chunks_carry_their_crc_and_rows_inflate_whole() {
+        // Wide enough for the image data to span several deflate blocks.
+        let (width, height) = (300_u32, 80_u32);
+        let pixels: Vec<[u8; 3]> = (0..width * height)
+            .map(|k| [k.to_le_bytes()[0], k.to_le_bytes()[1], 7])
+            .collect();
+        let mut png = Vec::new();
+        write(&mut png, width, height, &pixels, ColorSpace::Srgb).unwrap();
+
+        let chunks = chunks(&png);
+        for (kind, data, crc) in &chunks {
+            assert_eq!(*crc, crc32(kind.iter().chain(*data)), "{kind:?}");
+        }
+        let kinds: Vec<_> = chunks.iter().map(|(kind, ..)| *kind).collect();
+        assert_eq!(kinds.first(), Some(&&b"IHDR"[..]));
+        assert_eq!(kinds.last(), Some(&&b"IEND"[..]));
+
+        let idat: Vec<u8> = chunks
+            .iter()
+            .filter(|(kind, ..)| *kind == b"IDAT")
+            .flat_map(|(_, data, _)| data.iter().copied())
+            .collect();
+        let raw = inflate_stored(&idat);
+        let (w, h) = (width as usize, height as usize);
+        assert_eq!(raw.len(), h * (3 * w + 1));
+        for (row, line) in pixels.chunks(w).zip(raw.chunks(3 * w + 1)) {
+            assert_eq!(line[0], 0);
+            assert!(line[1..].iter().eq(row.iter().flatten()));
+        }
+    }
+}
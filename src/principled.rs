@@ -0,0 +1,175 @@
+use std::f64::consts::PI;
+
+use crate::{
+    color::{self, Color},
+    microfacet::{self, TrowbridgeReitz},
+    random,
+    vec3::Vec3,
+};
+
+/// Parameters of the Disney principled BSDF (Burley 2012, 2015), the common
+/// currency of material authoring tools. Apart from `base_color` and `ior`
+/// they all run from `0.0` to `1.0`.
+#[derive(Clone, Copy, Debug)]
+pub struct Principled {
+    pub base_color: Color,
+    /// Blends from a dielectric to a metal reflecting `base_color`.
+    pub metallic: f64,
+    pub roughness: f64,
+    /// Stretches the specular highlight along the surface tangent.
+    pub anisotropic: f64,
+    /// Strength of the dielectric specular; `0.5` reflects 4% head-on, as an
+    /// IOR of 1.5 does.
+    pub specular: f64,
+    /// Tints the dielectric specular towards the hue of `base_color`.
+    pub specular_tint: f64,
+    /// Extra reflection at grazing angles, for cloth.
+    pub sheen: f64,
+    pub sheen_tint: f64,
+    /// Strength of a second, colorless specular lobe, for varnish.
+    pub clearcoat: f64,
+    pub clearcoat_gloss: f64,
+    /// Turns the diffuse base into rough glass tinted by `base_color`.
+    pub transmission: f64,
+    /// Index of refraction of the transmission.
+    pub ior: f64,
+    /// Flattens the diffuse falloff the way light scattered under the
+    /// surface does.
+    pub subsurface: f64,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Self {
+            base_color: Color::new(0.8, 0.8, 0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+            anisotropic: 0.0,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            transmission: 0.0,
+            ior: 1.5,
+            subsurface: 0.0,
+        }
+    }
+}
+
+impl Principled {
+    /// Samples an incident direction for light leaving along `wo`, both in
+    /// the local shading frame, with the weight of the sample. `front_face`
+    /// tells whether `wo` is outside the surface.
+    ///
+    /// One lobe is picked in proportion to its estimated reflectance and its
+    /// weight divided by that probability.
+    pub fn sample(&self, wo: &Vec3, front_face: bool) -> Option<(Vec3, Color)> {
+        let distribution = TrowbridgeReitz::new(self.roughness, self.anisotropic);
+        // Each crossing of the glass takes half of the tint.
+        let transmission_tint = Color::new(
+            self.base_color.x().sqrt(),
+            self.base_color.y().sqrt(),
+            self.base_color.z().sqrt(),
+        );
+        if !front_face {
+            // Only light transmitted through the glass reaches the inside.
+            let (wi, weight) = distribution.sample_dielectric(wo, self.ior.recip())?;
+            return Some((wi, weight * transmission_tint));
+        }
+
+        let white = Color::new(1.0, 1.0, 1.0);
+        let luminance = color::luminance(&self.base_color);
+        let tint = if luminance > 0.0 {
+            self.base_color / luminance
+        } else {
+            white
+        };
+        let f0 = lerp(
+            self.specular * 0.08 * lerp(white, tint, self.specular_tint),
+            self.base_color,
+            self.metallic,
+        );
+        let transmission = (1.0 - self.metallic) * self.transmission;
+        let diffuse = (1.0 - self.metallic) * (1.0 - self.transmission);
+        // The glass lobe has its own reflection.
+        let specular = 1.0 - transmission;
+        let clearcoat = 0.25 * self.clearcoat;
+
+        let lobes = [
+            diffuse * (luminance + self.sheen),
+            specular * color::luminance(&microfacet::fresnel_schlick(wo.z(), f0)),
+            transmission,
+            clearcoat,
+        ];
+        let total: f64 = lobes.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut u = random::random::<f64>() * total;
+        let lobe = lobes
+            .iter()
+            .position(|&p| {
+                u -= p;
+                u < 0.0
+            })
+            .unwrap_or(lobes.len() - 1);
+        let probability = lobes[lobe] / total;
+
+        let (wi, weight) = match lobe {
+            0 => {
+                let wi = (Vec3::new(0.0, 0.0, 1.0) + Vec3::random_unit_vector()).unit();
+                if wi.z() <= 0.0 {
+                    return None;
+                }
+                let cos_d = Vec3::dot(&wi, &(wi + wo).unit());
+                let f_l = (1.0 - wi.z()).powi(5);
+                let f_v = (1.0 - wo.z()).powi(5);
+
+                let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+                let fd = (1.0 + (fd90 - 1.0) * f_l) * (1.0 + (fd90 - 1.0) * f_v);
+                // Hanrahan-Krueger approximation of single scattering.
+                let fss90 = self.roughness * cos_d * cos_d;
+                let fss = (1.0 + (fss90 - 1.0) * f_l) * (1.0 + (fss90 - 1.0) * f_v);
+                let ss = 1.25 * (fss * ((wi.z() + wo.z()).recip() - 0.5) + 0.5);
+                let retro = (1.0 - self.subsurface) * fd + self.subsurface * ss;
+
+                let sheen = self.sheen * lerp(white, tint, self.sheen_tint);
+                let sheen = PI * (1.0 - cos_d).powi(5) * sheen;
+                (wi, diffuse * (retro * self.base_color + sheen))
+            }
+            1 => {
+                let (wi, cos_theta, weight) = distribution.sample_reflection(wo)?;
+                (
+                    wi,
+                    specular * weight * microfacet::fresnel_schlick(cos_theta, f0),
+                )
+            }
+            2 => {
+                let (wi, weight) = distribution.sample_dielectric(wo, self.ior)?;
+                let tint = if wi.z() < 0.0 {
+                    transmission_tint
+                } else {
+                    white
+                };
+                (wi, transmission * weight * tint)
+            }
+            _ => {
+                let alpha = 0.1 + (0.001 - 0.1) * self.clearcoat_gloss;
+                let coat = TrowbridgeReitz::new(alpha.sqrt(), 0.0);
+                let (wi, cos_theta, weight) = coat.sample_reflection(wo)?;
+                let f0 = Color::new(0.04, 0.04, 0.04);
+                (
+                    wi,
+                    clearcoat * weight * microfacet::fresnel_schlick(cos_theta, f0),
+                )
+            }
+        };
+        Some((wi, weight / probability))
+    }
+}
+
+fn lerp(a: Color, b: Color, t: f64) -> Color {
+    (1.0 - t) * a + t * b
+}
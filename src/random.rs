@@ -0,0 +1,70 @@
+use std::cell::RefCell;
+
+use rand::{
+    distributions::{uniform::SampleUniform, Distribution, Standard},
+    Error, Rng, RngCore,
+};
+
+thread_local! {
+    static RNG: RefCell<SplitMix64> = RefCell::new(SplitMix64(rand::random()));
+}
+
+/// A counter-based generator: every output is the splitmix64 finalizer of
+/// the seed advanced by a fixed step. Reseeding it is free, which matters
+/// when every sample gets a seed of its own.
+struct SplitMix64(u64);
+
+impl RngCore for SplitMix64 {
+    fn next_u32(&mut self) -> u32 {
+        #[allow(clippy::cast_possible_truncation)]
+        let bits = (self.next_u64() >> 32) as u32;
+        bits
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        mix(self.0)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Restarts this thread's generator from `seed`, making everything drawn
+/// afterwards reproducible regardless of which thread does the work.
+pub fn reseed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = SplitMix64(seed));
+}
+
+pub fn random<T>() -> T
+where
+    Standard: Distribution<T>,
+{
+    RNG.with(|rng| rng.borrow_mut().gen())
+}
+
+pub fn gen_range<T: SampleUniform + PartialOrd>(range: std::ops::Range<T>) -> T {
+    RNG.with(|rng| rng.borrow_mut().gen_range(range))
+}
+
+/// Combines `values` into a well-mixed seed (splitmix64 finalizer).
+pub fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e37_79b9_7f4a_7c15, |h: u64, &v| {
+        mix((h ^ v).wrapping_add(0x9e37_79b9_7f4a_7c15))
+    })
+}
+
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -1,13 +1,35 @@
-use crate::vec3::{Point3, Vec3};
+use crate::{
+    medium::Medium,
+    vec3::{Point3, Vec3},
+};
 
+#[derive(Clone, Copy, Debug)]
 pub struct Ray {
     origin: Point3,
     direction: Vec3,
+    /// The single wavelength, in nanometers, the ray carries once dispersion
+    /// has split the light it started with.
+    wavelength: Option<f64>,
+    /// The medium the ray travels through, when inside a scattering object.
+    medium: Option<Medium>,
 }
 
 impl Ray {
     pub fn new(origin: Point3, direction: Vec3) -> Self {
-        Self { origin, direction }
+        Self {
+            origin,
+            direction,
+            wavelength: None,
+            medium: None,
+        }
+    }
+
+    pub fn with_wavelength(self, wavelength: Option<f64>) -> Self {
+        Self { wavelength, ..self }
+    }
+
+    pub fn with_medium(self, medium: Option<Medium>) -> Self {
+        Self { medium, ..self }
     }
 
     pub fn origin(&self) -> &Point3 {
@@ -18,6 +40,14 @@ impl Ray {
         &self.direction
     }
 
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+
+    pub fn medium(&self) -> Option<Medium> {
+        self.medium
+    }
+
     pub fn at(&self, t: f64) -> Vec3 {
         self.origin + t * self.direction
     }
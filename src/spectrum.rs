@@ -0,0 +1,214 @@
+use std::{
+    ops::{Add, Mul},
+    sync::OnceLock,
+};
+
+use crate::color::{self, Color, ColorSpace, Matrix};
+
+/// Wavelength range carried by the spectral renderer, in nanometers.
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+/// Number of wavelengths traced together along a path.
+pub const N: usize = 4;
+
+/// The wavelengths a path carries: a uniformly sampled hero wavelength and
+/// `N - 1` companions evenly rotated through the visible range.
+#[derive(Clone, Copy, Debug)]
+pub struct Wavelengths {
+    lambda: [f64; N],
+    /// Set once the path has gone through a dispersive interface, after
+    /// which only the hero wavelength remains meaningful.
+    secondary_terminated: bool,
+}
+
+impl Wavelengths {
+    /// Spreads the wavelengths from the hero picked by `u` in `0..1`.
+    pub fn sample(u: f64) -> Self {
+        #[allow(clippy::cast_precision_loss)]
+        let lambda = std::array::from_fn(|k| {
+            let offset = k as f64 / N as f64;
+            LAMBDA_MIN + ((u + offset) % 1.0) * (LAMBDA_MAX - LAMBDA_MIN)
+        });
+        Self {
+            lambda,
+            secondary_terminated: false,
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    /// Drops the companion wavelengths, which dispersion has sent along
+    /// paths of their own.
+    pub fn terminate_secondary(&mut self) {
+        self.secondary_terminated = true;
+    }
+}
+
+/// Values of a spectral distribution at the wavelengths of a path.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Spectrum([f64; N]);
+
+impl Spectrum {
+    /// Evaluates the spectrum `f` at each of `wavelengths`.
+    pub fn sample(wavelengths: &Wavelengths, f: impl Fn(f64) -> f64) -> Self {
+        Self(wavelengths.lambda.map(f))
+    }
+
+    /// Upsamples the RGB reflectance `c`, given in linear Rec. 709 primaries,
+    /// to a smooth spectrum (Smits 1999).
+    pub fn from_rgb(c: &Color, wavelengths: &Wavelengths) -> Self {
+        Self::sample(wavelengths, |lambda| smits(c, lambda))
+    }
+
+    /// Monte Carlo estimate of the CIE XYZ color of the spectrum, normalized
+    /// so that a constant spectrum of 1 has `Y = 1`.
+    pub fn to_xyz(self, wavelengths: &Wavelengths) -> Color {
+        let pdf = (LAMBDA_MAX - LAMBDA_MIN).recip();
+        let count = if wavelengths.secondary_terminated {
+            1
+        } else {
+            N
+        };
+        let sum = wavelengths
+            .lambda
+            .iter()
+            .zip(&self.0)
+            .take(count)
+            .fold(Color::default(), |sum, (&lambda, &value)| {
+                sum + value * cie_xyz(lambda)
+            });
+        #[allow(clippy::cast_precision_loss)]
+        let count = count as f64;
+        sum / (count * pdf * CIE_Y_INTEGRAL)
+    }
+}
+
+impl Add for Spectrum {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self(std::array::from_fn(|k| self.0[k] + rhs.0[k]))
+    }
+}
+
+impl Mul for Spectrum {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self(std::array::from_fn(|k| self.0[k] * rhs.0[k]))
+    }
+}
+
+impl Mul<Spectrum> for f64 {
+    type Output = Spectrum;
+
+    fn mul(self, rhs: Spectrum) -> Spectrum {
+        Spectrum(rhs.0.map(|value| self * value))
+    }
+}
+
+/// Matrix taking the XYZ estimates of `Spectrum::to_xyz` to linear RGB in
+/// `space`, scaled per channel so that a constant spectrum comes out white.
+pub fn xyz_to_rgb(space: ColorSpace) -> Matrix {
+    let m = space.xyz_to_rgb();
+    // A constant spectrum has the color of the CIE matching function
+    // integrals, the equal-energy white.
+    let steps = 4700_u32;
+    let dl = (LAMBDA_MAX - LAMBDA_MIN) / f64::from(steps);
+    let white = (0..steps).fold(Color::default(), |sum, k| {
+        sum + cie_xyz(LAMBDA_MIN + (f64::from(k) + 0.5) * dl) * dl
+    }) / CIE_Y_INTEGRAL;
+    let white = color::transform(&m, &white);
+    let scale = [white.x(), white.y(), white.z()];
+    std::array::from_fn(|i| m[i].map(|value| value / scale[i]))
+}
+
+/// Linear sRGB color of light at the single wavelength `lambda`, weighted so
+/// that it averages to white over uniformly sampled wavelengths. Lets the RGB
+/// renderer follow one wavelength through dispersive materials.
+pub fn wavelength_color(lambda: f64) -> Color {
+    static XYZ_TO_SRGB: OnceLock<Matrix> = OnceLock::new();
+    let m = XYZ_TO_SRGB.get_or_init(|| xyz_to_rgb(ColorSpace::Srgb));
+    let pdf = (LAMBDA_MAX - LAMBDA_MIN).recip();
+    color::transform(m, &(cie_xyz(lambda) / (pdf * CIE_Y_INTEGRAL)))
+}
+
+/// Integral of the CIE 1931 `ȳ` matching function over the visible range.
+const CIE_Y_INTEGRAL: f64 = 106.856_895;
+
+/// CIE 1931 2° color matching functions at `lambda`, using the multi-lobe
+/// Gaussian fit of Wyman, Sloan and Shirley (2013).
+fn cie_xyz(lambda: f64) -> Color {
+    let g = |mu: f64, sigma_below: f64, sigma_above: f64| {
+        let sigma = if lambda < mu {
+            sigma_below
+        } else {
+            sigma_above
+        };
+        let t = (lambda - mu) / sigma;
+        (-0.5 * t * t).exp()
+    };
+    Color::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+/// Smits' basis spectra, tabulated in ten equal bins from 380 to 720 nm.
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+/// Value at `lambda` of Smits' spectrum for `c`: white scaled by the smallest
+/// channel, plus the secondary and primary colors making up the rest.
+fn smits(c: &Color, lambda: f64) -> f64 {
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    let bin = (((lambda - 380.0) / 34.0).max(0.0) as usize).min(9);
+    let (r, g, b) = (c.x(), c.y(), c.z());
+
+    if r <= g && r <= b {
+        let rest = if g <= b {
+            (g - r) * SMITS_CYAN[bin] + (b - g) * SMITS_BLUE[bin]
+        } else {
+            (b - r) * SMITS_CYAN[bin] + (g - b) * SMITS_GREEN[bin]
+        };
+        r * SMITS_WHITE[bin] + rest
+    } else if g <= r && g <= b {
+        let rest = if r <= b {
+            (r - g) * SMITS_MAGENTA[bin] + (b - r) * SMITS_BLUE[bin]
+        } else {
+            (b - g) * SMITS_MAGENTA[bin] + (r - b) * SMITS_RED[bin]
+        };
+        g * SMITS_WHITE[bin] + rest
+    } else {
+        let rest = if r <= g {
+            (r - b) * SMITS_YELLOW[bin] + (g - r) * SMITS_GREEN[bin]
+        } else {
+            (g - b) * SMITS_YELLOW[bin] + (r - g) * SMITS_RED[bin]
+        };
+        b * SMITS_WHITE[bin] + rest
+    }
+}
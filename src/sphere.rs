@@ -1,9 +1,10 @@
-use std::sync::Arc;
+use std::{f64::consts::PI, sync::Arc};
 
 use crate::{
     hittable::{HitRecord, Hittable},
     interval::Interval,
     material::Material,
+    random,
     ray::Ray,
     vec3::{Point3, Vec3},
 };
@@ -22,6 +23,32 @@ impl Sphere {
             mat,
         }
     }
+
+    /// Texture coordinates of the point with unit normal `n`: `u` runs
+    /// around the `y` axis from `-x`, `v` from the bottom pole to the top.
+    fn uv(n: &Vec3) -> (f64, f64) {
+        let theta = (-n.y()).acos();
+        let phi = (-n.z()).atan2(n.x()) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    /// `∂p/∂u` and `∂p/∂v` at the point with unit normal `n`, following
+    /// `uv`; `None` at the poles, where they vanish.
+    fn derivatives(&self, n: &Vec3) -> Option<(Vec3, Vec3)> {
+        let sin_theta = (1.0 - n.y() * n.y()).max(0.0).sqrt();
+        if sin_theta < 1e-8 {
+            return None;
+        }
+        let dpdu = 2.0 * PI * self.radius * Vec3::new(n.z(), 0.0, -n.x());
+        let dpdv = PI
+            * self.radius
+            * Vec3::new(
+                -n.y() * n.x() / sin_theta,
+                sin_theta,
+                -n.y() * n.z() / sin_theta,
+            );
+        Some((dpdu, dpdv))
+    }
 }
 
 impl Hittable for Sphere {
@@ -38,23 +65,27 @@ impl Hittable for Sphere {
 
         let sqrtd = f64::sqrt(discriminant);
 
-        let mut root = (h - sqrtd) / a;
-        if !ray_t.surrounds(root) {
-            root = (h + sqrtd) / a;
+        for root in [(h - sqrtd) / a, (h + sqrtd) / a] {
             if !ray_t.surrounds(root) {
-                return None;
-            };
-        }
+                continue;
+            }
 
-        let p = ray.at(root);
-        let outward_normal = (p - self.center) / self.radius;
-        let rec = HitRecord::new(
-            root,
-            ray.at(root),
-            ray,
-            outward_normal,
-            Arc::clone(&self.mat),
-        );
-        Some(rec)
+            let p = ray.at(root);
+            let outward_normal = (p - self.center) / self.radius;
+            let (u, v) = Self::uv(&outward_normal);
+            // Cutouts let the ray through to the far side or beyond.
+            let opacity = self.mat.opacity(u, v, &p);
+            if opacity < 1.0 && random::random::<f64>() >= opacity {
+                continue;
+            }
+
+            let mut rec = HitRecord::new(root, p, ray, outward_normal, Arc::clone(&self.mat));
+            (rec.u, rec.v) = (u, v);
+            if let Some((tangent, bitangent)) = self.derivatives(&outward_normal) {
+                (rec.tangent, rec.bitangent) = (tangent, bitangent);
+            }
+            return Some(rec);
+        }
+        None
     }
 }
@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use crate::{
+    color::{self, Color},
+    image::Image,
+    vec3::Point3,
+};
+
+/// A color that varies over a surface, looked up by texture coordinates
+/// `(u, v)` or by the hit point.
+#[derive(Clone, Debug)]
+#[allow(unused)]
+pub enum Texture {
+    Constant(Color),
+    /// Alternates `even` and `odd` in cubes `scale` wide through space.
+    Checker {
+        scale: f64,
+        even: Color,
+        odd: Color,
+    },
+    /// An image stretched over the `0..1` texture coordinates, `v` running
+    /// up from its bottom row. Its colors are taken to be sRGB encoded.
+    Image(Arc<Image>),
+}
+
+impl Texture {
+    /// The color at texture coordinates `(u, v)` and point `p`, linear.
+    #[allow(unused)]
+    pub fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let c = self.lookup(u, v, p);
+        match self {
+            Self::Image(_) => color::srgb_to_linear(&c),
+            _ => c,
+        }
+    }
+
+    /// A single factor from the texture, the average of its channels.
+    /// Factors are data, such as masks, weights and heights, so image values
+    /// are used as stored rather than decoded from sRGB.
+    pub fn factor(&self, u: f64, v: f64, p: &Point3) -> f64 {
+        let c = self.lookup(u, v, p);
+        (c.x() + c.y() + c.z()) / 3.0
+    }
+
+    /// Like `factor`, but interpolating between the pixels of images, for
+    /// values that get differentiated, such as heights.
+    pub fn smooth_factor(&self, u: f64, v: f64, p: &Point3) -> f64 {
+        match self {
+            Self::Image(image) => image.gray_uv(u, v),
+            _ => self.factor(u, v, p),
+        }
+    }
+
+    /// Size of an image pixel in texture coordinates, along `u` and `v`.
+    pub fn texel_size(&self) -> Option<(f64, f64)> {
+        match self {
+            Self::Image(image) => Some((
+                f64::from(image.width()).recip(),
+                f64::from(image.height()).recip(),
+            )),
+            _ => None,
+        }
+    }
+
+    /// The value at `(u, v)` and `p`, as stored.
+    fn lookup(&self, u: f64, v: f64, p: &Point3) -> Color {
+        match self {
+            Self::Constant(c) => *c,
+            Self::Checker { scale, even, odd } => {
+                #[allow(clippy::cast_possible_truncation)]
+                let cell = |x: f64| (x / scale).floor() as i64;
+                if (cell(p.x()) + cell(p.y()) + cell(p.z())) % 2 == 0 {
+                    *even
+                } else {
+                    *odd
+                }
+            }
+            Self::Image(image) => match *image.at_uv(u, v) {
+                [r, g, b] => Color::new(r, g, b),
+                [gray] => Color::new(gray, gray, gray),
+                _ => Color::default(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn factors_skip_the_srgb_decoding_of_colors() {
+        let path =
+            std::env::temp_dir().join(format!("ray-tracing-gray-{}.pgm", std::process::id()));
+        fs::write(&path, "P2 1 1 100 50\n").unwrap();
+        let texture = Texture::Image(Arc::new(Image::load(&path).unwrap()));
+        fs::remove_file(&path).unwrap();
+
+        let p = Point3::default();
+        assert!((texture.factor(0.5, 0.5, &p) - 0.5).abs() < 1e-12);
+        assert!((texture.value(0.5, 0.5, &p).x() - 0.214).abs() < 1e-3);
+    }
+}
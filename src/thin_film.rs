@@ -0,0 +1,199 @@
+use std::{
+    f64::consts::PI,
+    ops::{Add, Div, Mul, Sub},
+};
+
+use crate::{
+    color::Color,
+    spectrum::{self, LAMBDA_MAX, LAMBDA_MIN},
+    texture::Texture,
+    vec3::Point3,
+};
+
+/// Wavelengths averaged over to find the color of the interference.
+const WAVELENGTH_SAMPLES: u32 = 32;
+
+/// A thin transparent coating, such as a soap film or an oil slick, whose
+/// reflections interfere into iridescent colors.
+#[derive(Clone, Debug)]
+pub struct ThinFilm {
+    /// Index of refraction of the film.
+    pub ior: f64,
+    /// Thickness of the film in nanometers.
+    pub thickness: f64,
+    /// Scales `thickness` over the surface, for swirls and runs.
+    pub thickness_map: Option<Texture>,
+}
+
+impl ThinFilm {
+    #[allow(unused)]
+    pub fn new(ior: f64, thickness: f64) -> Self {
+        Self {
+            ior,
+            thickness,
+            thickness_map: None,
+        }
+    }
+
+    /// RGB reflectance of the film lying on a substrate of index
+    /// `substrate(λ)`, for light arriving from a medium of index `eta_i` at
+    /// `cos_theta` to the normal, at the point `p` with texture coordinates
+    /// `(u, v)`.
+    ///
+    /// Light of a single `wavelength`, as spectral paths carry, gets the
+    /// reflectance at that wavelength; otherwise the interference colors are
+    /// averaged over the visible range.
+    pub fn reflectance(
+        &self,
+        cos_theta: f64,
+        eta_i: f64,
+        substrate: impl Fn(f64) -> Complex,
+        wavelength: Option<f64>,
+        (u, v, p): (f64, f64, &Point3),
+    ) -> Color {
+        let thickness = self.thickness
+            * self
+                .thickness_map
+                .as_ref()
+                .map_or(1.0, |map| map.factor(u, v, p));
+        let reflectance = |lambda| {
+            airy(
+                cos_theta,
+                eta_i,
+                self.ior,
+                substrate(lambda),
+                thickness,
+                lambda,
+            )
+        };
+
+        if let Some(lambda) = wavelength {
+            let r = reflectance(lambda);
+            return Color::new(r, r, r);
+        }
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / f64::from(WAVELENGTH_SAMPLES);
+        let sum = (0..WAVELENGTH_SAMPLES).fold(Color::default(), |sum, k| {
+            let lambda = LAMBDA_MIN + (f64::from(k) + 0.5) * step;
+            sum + reflectance(lambda) * spectrum::wavelength_color(lambda)
+        });
+        sum / f64::from(WAVELENGTH_SAMPLES)
+    }
+}
+
+/// Reflectance at `lambda` nanometers of a film of index `eta_film` and
+/// `thickness` nanometers between a medium of index `eta_i` and a substrate
+/// of complex index `eta_t`, averaging both polarizations and summing every
+/// internal reflection (Airy).
+fn airy(
+    cos_theta: f64,
+    eta_i: f64,
+    eta_film: f64,
+    eta_t: Complex,
+    thickness: f64,
+    lambda: f64,
+) -> f64 {
+    let cos_i = cos_theta.clamp(0.0, 1.0);
+    let sin2 = eta_i * eta_i * (1.0 - cos_i * cos_i);
+    let n_i = Complex::real(eta_i);
+    let n_f = Complex::real(eta_film);
+    // Snell's law with complex indices: cos θ = √(1 - (η_i sin θ_i / η)²).
+    let cos_in = |n: Complex| (Complex::real(1.0) - Complex::real(sin2) / (n * n)).sqrt();
+    let cos_i = Complex::real(cos_i);
+    let cos_f = cos_in(n_f);
+    let cos_t = cos_in(eta_t);
+
+    // Phase change over one round trip through the film.
+    let phase = (Complex::real(4.0 * PI * thickness / lambda) * n_f * cos_f).exp_i();
+
+    let mut sum = 0.0;
+    for polarization in 0..2 {
+        let fresnel = |n1: Complex, c1: Complex, n2: Complex, c2: Complex| {
+            if polarization == 0 {
+                (n1 * c1 - n2 * c2) / (n1 * c1 + n2 * c2)
+            } else {
+                (n2 * c1 - n1 * c2) / (n2 * c1 + n1 * c2)
+            }
+        };
+        let r_if = fresnel(n_i, cos_i, n_f, cos_f);
+        let r_ft = fresnel(n_f, cos_f, eta_t, cos_t);
+        let r = (r_if + r_ft * phase) / (Complex::real(1.0) + r_if * r_ft * phase);
+        sum += r.norm2();
+    }
+    (sum / 2.0).clamp(0.0, 1.0)
+}
+
+/// A complex number, for indices of refraction `η + iκ` and the phases of
+/// interfering waves.
+#[derive(Clone, Copy, Debug)]
+pub struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    fn real(re: f64) -> Self {
+        Self::new(re, 0.0)
+    }
+
+    fn norm2(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// `e^(iz)`.
+    fn exp_i(self) -> Self {
+        let scale = (-self.im).exp();
+        let (sin, cos) = self.re.sin_cos();
+        Self::new(scale * cos, scale * sin)
+    }
+
+    /// The principal square root.
+    fn sqrt(self) -> Self {
+        let n = self.norm2().sqrt();
+        let re = (0.5 * (n + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (n - self.re)).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        let d = rhs.norm2();
+        Self::new(
+            (self.re * rhs.re + self.im * rhs.im) / d,
+            (self.im * rhs.re - self.re * rhs.im) / d,
+        )
+    }
+}
@@ -0,0 +1,144 @@
+/// The order in which tiles are handed out to the render threads.
+#[derive(Clone, Copy, Debug, Default)]
+#[allow(unused)]
+pub enum TileOrder {
+    /// Left to right, top to bottom.
+    #[default]
+    Scanline,
+    /// Along a Hilbert curve, so consecutive tiles stay spatially close.
+    Hilbert,
+    /// Outwards from the center of the image, where the subject usually is.
+    Spiral,
+}
+
+/// A rectangle of pixels, `x0..x1` by `y0..y1`.
+#[derive(Clone, Copy, Debug)]
+pub struct Tile {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl Tile {
+    pub fn width(&self) -> u32 {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> u32 {
+        self.y1 - self.y0
+    }
+
+    pub fn len(&self) -> u32 {
+        self.width() * self.height()
+    }
+
+    /// Grows the tile by `margin` pixels on every side, staying inside `within`.
+    pub fn expand(&self, margin: u32, within: &Tile) -> Tile {
+        Tile {
+            x0: self.x0.saturating_sub(margin).max(within.x0),
+            y0: self.y0.saturating_sub(margin).max(within.y0),
+            x1: (self.x1 + margin).min(within.x1),
+            y1: (self.y1 + margin).min(within.y1),
+        }
+    }
+
+    /// The part of the tile inside `other`, possibly empty.
+    pub fn intersect(&self, other: &Tile) -> Tile {
+        let x0 = self.x0.max(other.x0);
+        let y0 = self.y0.max(other.y0);
+        Tile {
+            x0,
+            y0,
+            x1: self.x1.min(other.x1).max(x0),
+            y1: self.y1.min(other.y1).max(y0),
+        }
+    }
+
+    /// Pixel coordinates covered by the tile, row by row.
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        (self.y0..self.y1).flat_map(move |j| (self.x0..self.x1).map(move |i| (i, j)))
+    }
+}
+
+/// Splits `bounds` into tiles of at most `tile_size` pixels square, listed
+/// in `order`.
+pub fn tiles(bounds: Tile, tile_size: u32, order: TileOrder) -> Vec<Tile> {
+    let tile_size = tile_size.max(1);
+    let cols = bounds.width().div_ceil(tile_size);
+    let rows = bounds.height().div_ceil(tile_size);
+
+    let grid: Vec<(u32, u32)> = match order {
+        TileOrder::Scanline => (0..rows)
+            .flat_map(|y| (0..cols).map(move |x| (x, y)))
+            .collect(),
+        TileOrder::Hilbert => {
+            let side = cols.max(rows).next_power_of_two();
+            let mut grid: Vec<_> = (0..rows)
+                .flat_map(|y| (0..cols).map(move |x| (x, y)))
+                .collect();
+            grid.sort_by_key(|&(x, y)| hilbert_index(side, x, y));
+            grid
+        }
+        TileOrder::Spiral => spiral(cols, rows),
+    };
+
+    grid.into_iter()
+        .map(|(x, y)| Tile {
+            x0: bounds.x0 + x * tile_size,
+            y0: bounds.y0 + y * tile_size,
+            x1: (bounds.x0 + (x + 1) * tile_size).min(bounds.x1),
+            y1: (bounds.y0 + (y + 1) * tile_size).min(bounds.y1),
+        })
+        .collect()
+}
+
+/// Distance of `(x, y)` along the Hilbert curve filling a `side` by `side` grid.
+fn hilbert_index(side: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut d = 0;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = u32::from(x & s > 0);
+        let ry = u32::from(y & s > 0);
+        d += u64::from(s) * u64::from(s) * u64::from((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+/// Walks a square spiral out of the center cell, keeping the cells inside the grid.
+fn spiral(cols: u32, rows: u32) -> Vec<(u32, u32)> {
+    const DIRECTIONS: [(i64, i64); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+    let total = usize::try_from(cols * rows).expect("too many tiles");
+    let (cols, rows) = (i64::from(cols), i64::from(rows));
+    let mut cells = Vec::with_capacity(total);
+    let (mut x, mut y) = ((cols - 1) / 2, (rows - 1) / 2);
+    let mut run = 1;
+    let mut direction = 0;
+
+    while cells.len() < total {
+        for _ in 0..2 {
+            let (dx, dy) = DIRECTIONS[direction];
+            for _ in 0..run {
+                if (0..cols).contains(&x) && (0..rows).contains(&y) {
+                    #[allow(clippy::cast_possible_truncation)]
+                    #[allow(clippy::cast_sign_loss)]
+                    cells.push((x as u32, y as u32));
+                }
+                x += dx;
+                y += dy;
+            }
+            direction = (direction + 1) % 4;
+        }
+        run += 1;
+    }
+    cells
+}
@@ -0,0 +1,125 @@
+use crate::color::{self, Color};
+
+/// Compresses linear scene radiance into the displayable `0..1` range.
+#[derive(Clone, Copy, Debug, Default)]
+#[allow(unused)]
+pub enum ToneMap {
+    /// No compression; values above 1 are clipped.
+    #[default]
+    Clamp,
+    /// `L / (1 + L)` on luminance, keeping the hue.
+    Reinhard,
+    /// Reinhard with luminance `white` mapped to pure white instead of only
+    /// approaching it.
+    ExtendedReinhard { white: f64 },
+    /// Fit of the ACES reference rendering and sRGB output transforms.
+    Aces,
+    /// John Hable's filmic curve from Uncharted 2.
+    Hable,
+    /// Troy Sobotka's AgX, through its polynomial contrast approximation.
+    Agx,
+}
+
+impl ToneMap {
+    pub fn apply(&self, c: Color) -> Color {
+        match *self {
+            Self::Clamp => c,
+            Self::Reinhard => scale_luminance(c, |l| l / (1.0 + l)),
+            Self::ExtendedReinhard { white } => {
+                scale_luminance(c, |l| l * (1.0 + l / (white * white)) / (1.0 + l))
+            }
+            Self::Aces => aces(c),
+            Self::Hable => {
+                const WHITE: f64 = 11.2;
+                const EXPOSURE_BIAS: f64 = 2.0;
+                let scale = hable(WHITE).recip();
+                map_channels(c, |x| hable(EXPOSURE_BIAS * x) * scale)
+            }
+            Self::Agx => agx(c),
+        }
+    }
+}
+
+fn map_channels(c: Color, f: impl Fn(f64) -> f64) -> Color {
+    Color::new(f(c.x()), f(c.y()), f(c.z()))
+}
+
+fn scale_luminance(c: Color, f: impl Fn(f64) -> f64) -> Color {
+    let l = color::luminance(&c);
+    if l <= 0.0 {
+        return Color::default();
+    }
+    c * (f(l) / l)
+}
+
+fn hable(x: f64) -> f64 {
+    const A: f64 = 0.15;
+    const B: f64 = 0.50;
+    const C: f64 = 0.10;
+    const D: f64 = 0.20;
+    const E: f64 = 0.02;
+    const F: f64 = 0.30;
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+/// Stephen Hill's fit, working in the ACES AP1 space.
+fn aces(c: Color) -> Color {
+    const INPUT: [[f64; 3]; 3] = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    const OUTPUT: [[f64; 3]; 3] = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+
+    let c = color::transform(&INPUT, &c);
+    let c = map_channels(c, |v| {
+        (v * (v + 0.024_578_6) - 0.000_090_537) / (v * (0.983_729 * v + 0.432_951) + 0.238_081)
+    });
+    map_channels(color::transform(&OUTPUT, &c), |v| v.clamp(0.0, 1.0))
+}
+
+/// AgX base look, returned in linear light for the output encoding.
+fn agx(c: Color) -> Color {
+    const INSET: [[f64; 3]; 3] = [
+        [0.842_479_062_253_094, 0.078_433_6, 0.079_223_745_147_764_3],
+        [
+            0.042_328_242_261_012_3,
+            0.878_468_636_469_772,
+            0.079_166_127_460_543_4,
+        ],
+        [0.042_375_654_905_705_1, 0.078_433_6, 0.879_142_973_793_104],
+    ];
+    const OUTSET: [[f64; 3]; 3] = [
+        [
+            1.196_879_005_120_17,
+            -0.098_020_881_140_136_8,
+            -0.099_029_744_079_720_5,
+        ],
+        [
+            -0.052_896_851_757_456_2,
+            1.151_903_129_904_17,
+            -0.098_961_176_844_843_3,
+        ],
+        [
+            -0.052_971_635_514_443_8,
+            -0.098_043_450_117_124_1,
+            1.151_073_672_641_16,
+        ],
+    ];
+    const MIN_EV: f64 = -12.473_93;
+    const MAX_EV: f64 = 4.026_069;
+
+    let c = color::transform(&INSET, &c);
+    let c = map_channels(c, |v| {
+        let v = (v.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let v2 = v * v;
+        let v4 = v2 * v2;
+        15.5 * v4 * v2 - 40.14 * v4 * v + 31.96 * v4 - 6.868 * v2 * v + 0.4298 * v2 + 0.1191 * v
+            - 0.00232
+    });
+    map_channels(color::transform(&OUTSET, &c), |v| v.max(0.0).powf(2.2))
+}
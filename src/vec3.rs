@@ -1,4 +1,4 @@
-use rand::Rng;
+use crate::random;
 
 #[derive(Default, Clone, Copy, Debug)]
 pub struct Vec3 {
@@ -13,7 +13,7 @@ impl Vec3 {
         Vec3 { e: [0.0, 0.0, 0.0] }
     }
 
-    pub fn new(x: f64, y: f64, z: f64) -> Self {
+    pub const fn new(x: f64, y: f64, z: f64) -> Self {
         Vec3 { e: [x, y, z] }
     }
 
@@ -38,12 +38,11 @@ impl Vec3 {
     }
 
     pub fn random_unit_vector() -> Self {
-        let mut rng = rand::thread_rng();
         loop {
             let v = Vec3::new(
-                rng.gen_range(-1.0..1.0),
-                rng.gen_range(-1.0..1.0),
-                rng.gen_range(-1.0..1.0),
+                random::gen_range(-1.0..1.0),
+                random::gen_range(-1.0..1.0),
+                random::gen_range(-1.0..1.0),
             );
 
             if v.len_squared() < 1.0 {
@@ -54,9 +53,12 @@ impl Vec3 {
     }
 
     pub fn random_in_unit_disk() -> Self {
-        let mut rng = rand::thread_rng();
         loop {
-            let p = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
+            let p = Vec3::new(
+                random::gen_range(-1.0..1.0),
+                random::gen_range(-1.0..1.0),
+                0.0,
+            );
             if p.len_squared() < 1.0 {
                 break p;
             }